@@ -0,0 +1,42 @@
+extern crate td_proto_rust as td_rp;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+fn load(path: &str) -> td_rp::Config {
+    let config = if Path::new(path).is_dir() {
+        td_rp::Config::from_dir(path)
+    } else {
+        td_rp::Config::from_file(path)
+    };
+    match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("load {} failed: {}", path, err);
+            process::exit(2);
+        }
+    }
+}
+
+/// compare the old and new protocol files or dirs, exit with 1 if there are breaking changes
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("usage: {} <old protocol> <new protocol>", args[0]);
+        process::exit(2);
+    }
+    let changes = td_rp::check_compatible(&load(&args[1]), &load(&args[2]));
+    let mut breaking = false;
+    for change in &changes {
+        if change.is_breaking() {
+            breaking = true;
+            println!("breaking: {}", change);
+        } else {
+            println!("safe: {}", change);
+        }
+    }
+    if breaking {
+        process::exit(1);
+    }
+}
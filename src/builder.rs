@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use Config;
+use Field;
+use Proto;
+use RpResult;
+use RpError;
+use ErrorKind;
+use get_canonical_pattern;
+
+/// build the Config without json, the first problem is kept and returned by build
+pub struct ConfigBuilder {
+    field: HashMap<String, Field>,
+    schemas: HashMap<String, HashMap<String, Field>>,
+    enums: HashMap<String, HashMap<String, u16>>,
+    proto: HashMap<String, Proto>,
+    index_proto: HashMap<u16, String>,
+    error: Option<RpError>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            field: HashMap::new(),
+            schemas: HashMap::new(),
+            enums: HashMap::new(),
+            proto: HashMap::new(),
+            index_proto: HashMap::new(),
+            error: None,
+        }
+    }
+
+    pub fn field(mut self, name: &str, index: u16, pattern: &str) -> ConfigBuilder {
+        if self.error.is_none() {
+            if let Err(err) = add_field(&mut self.field, "field", name, index, pattern) {
+                self.error = Some(err);
+            }
+        }
+        self
+    }
+
+    /// add the field to the schema, the schema is created by the first field
+    pub fn schema_field(mut self,
+                        schema: &str,
+                        name: &str,
+                        index: u16,
+                        pattern: &str)
+                        -> ConfigBuilder {
+        if self.error.is_none() {
+            let prefix = format!("schema {} field", schema);
+            let fields = self.schemas.entry(schema.to_string()).or_insert_with(HashMap::new);
+            if let Err(err) = add_field(fields, &prefix, name, index, pattern) {
+                self.error = Some(err);
+            }
+        }
+        self
+    }
+
+    /// add the names and values of the enum, the names are added to the defined enum
+    pub fn enum_values(mut self, name: &str, values: &[(&str, u16)]) -> ConfigBuilder {
+        let enum_values = self.enums.entry(name.to_string()).or_insert_with(HashMap::new);
+        for &(value_name, value) in values {
+            enum_values.insert(value_name.to_string(), value);
+        }
+        self
+    }
+
+    pub fn proto(self, name: &str, index: u16, args: &[&str]) -> ConfigBuilder {
+        let args: Vec<(&str, &str)> = args.iter().map(|arg| ("", *arg)).collect();
+        self.proto_named(name, index, &args)
+    }
+
+    /// add the proto whose args are (arg name, pattern)
+    pub fn proto_named(mut self, name: &str, index: u16, args: &[(&str, &str)]) -> ConfigBuilder {
+        if self.error.is_none() {
+            if let Err(err) = self.add_proto(name, index, args) {
+                self.error = Some(err);
+            }
+        }
+        self
+    }
+
+    /// the Config, or the first problem found while building
+    pub fn build(self) -> RpResult<Config> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let mut config = Config::new_by_map(self.field, self.proto);
+        for (name, fields) in self.schemas {
+            config.add_schema(name, fields);
+        }
+        for (name, values) in self.enums {
+            config.add_enum(name, values);
+        }
+        let problems = config.validate();
+        if !problems.is_empty() {
+            fail!((ErrorKind::ConfigError, "invalid schema", problems.join("; ")));
+        }
+        Ok(config)
+    }
+
+    fn add_proto(&mut self, name: &str, index: u16, args: &[(&str, &str)]) -> RpResult<()> {
+        ensure!(!self.proto.contains_key(name),
+                (ErrorKind::ConfigError, "bad proto", format!("proto {} is defined", name)));
+        if let Some(other) = self.index_proto.get(&index) {
+            fail!((ErrorKind::ConfigError,
+                   "bad proto",
+                   format!("proto {} and {} share index {}", other, name, index)));
+        }
+        let mut patterns = vec![];
+        let mut arg_names = vec![];
+        for (i, &(arg_name, arg)) in args.iter().enumerate() {
+            let pattern = unwrap_or!(get_canonical_pattern(arg), {
+                fail!((ErrorKind::ConfigError,
+                       "bad proto",
+                       format!("proto {} arg {} pattern {} is unknown", name, i, arg)))
+            });
+            patterns.push(pattern);
+            arg_names.push(arg_name.to_string());
+        }
+        self.index_proto.insert(index, name.to_string());
+        self.proto.insert(name.to_string(),
+                          Proto {
+                              msg_type: String::new(),
+                              args: patterns,
+                              arg_names: arg_names,
+                              index: Some(index),
+                              direction: None,
+                              deprecated: false,
+                              desc: String::new(),
+                          });
+        Ok(())
+    }
+}
+
+fn add_field(fields: &mut HashMap<String, Field>,
+             prefix: &str,
+             name: &str,
+             index: u16,
+             pattern: &str)
+             -> RpResult<()> {
+    ensure!(!fields.contains_key(name),
+            (ErrorKind::ConfigError, "bad field", format!("{} {} is defined", prefix, name)));
+    ensure!(index != 0,
+            (ErrorKind::ConfigError,
+             "bad field",
+             format!("{} {} index 0 is reserved", prefix, name)));
+    if let Some((other, _)) = fields.iter().find(|&(_, field)| field.index == index) {
+        fail!((ErrorKind::ConfigError,
+               "bad field",
+               format!("{} {} and {} share index {}", prefix, other, name, index)));
+    }
+    let pattern = unwrap_or!(get_canonical_pattern(pattern), {
+        fail!((ErrorKind::ConfigError,
+               "bad field",
+               format!("{} {} pattern {} is unknown", prefix, name, pattern)))
+    });
+    fields.insert(name.to_string(),
+                  Field {
+                      index: index,
+                      pattern: pattern,
+                      scale: None,
+                      required: false,
+                      default: None,
+                      deprecated: false,
+                      desc: String::new(),
+                  });
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::collections::HashMap;
+
+use Config;
+use Field;
+
+/// one difference between the old and the new config
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum Change {
+    /// a new field name with a new index
+    FieldAdded(String),
+    /// the field name and its index are both gone
+    FieldRemoved(String),
+    /// the index is kept but the field name changed, (index, old name, new name)
+    FieldRenamed(u16, String, String),
+    /// the index is reused with another pattern, (new name, old pattern, new pattern)
+    FieldPatternChanged(String, String, String),
+    /// the field name is kept but the index changed, (name, old index, new index)
+    FieldIndexChanged(String, u16, u16),
+    /// the field is made required or optional, a new required field counts as made required,
+    /// (new name, old required, new required)
+    FieldRequiredChanged(String, bool, bool),
+    /// the name of the enum value is kept but the value changed, (name, old value, new value)
+    EnumValueChanged(String, u16, u16),
+    /// the name of the enum value is gone, the value decodes to another name or the number
+    EnumValueRemoved(String),
+    ProtoAdded(String),
+    ProtoRemoved(String),
+    /// (name, old args, new args)
+    ProtoArgsChanged(String, Vec<String>, Vec<String>),
+    /// (name, old index, new index)
+    ProtoIndexChanged(String, u16, Option<u16>),
+}
+
+impl Change {
+    /// the data encoded by one config can't be decoded correctly by the other
+    pub fn is_breaking(&self) -> bool {
+        match *self {
+            Change::FieldAdded(_) | Change::FieldRemoved(_) | Change::ProtoAdded(_) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::FieldAdded(ref name) => write!(f, "field {} added", name),
+            Change::FieldRemoved(ref name) => write!(f, "field {} removed", name),
+            Change::FieldRenamed(index, ref old, ref new) => {
+                write!(f, "field index {} renamed from {} to {}", index, old, new)
+            }
+            Change::FieldPatternChanged(ref name, ref old, ref new) => {
+                write!(f, "field {} pattern changed from {} to {}", name, old, new)
+            }
+            Change::FieldIndexChanged(ref name, old, new) => {
+                write!(f, "field {} index changed from {} to {}", name, old, new)
+            }
+            Change::FieldRequiredChanged(ref name, old, new) => {
+                write!(f, "field {} required changed from {} to {}", name, old, new)
+            }
+            Change::EnumValueChanged(ref name, old, new) => {
+                write!(f, "enum value {} changed from {} to {}", name, old, new)
+            }
+            Change::EnumValueRemoved(ref name) => write!(f, "enum value {} removed", name),
+            Change::ProtoAdded(ref name) => write!(f, "proto {} added", name),
+            Change::ProtoRemoved(ref name) => write!(f, "proto {} removed", name),
+            Change::ProtoArgsChanged(ref name, ref old, ref new) => {
+                write!(f, "proto {} args changed from {:?} to {:?}", name, old, new)
+            }
+            Change::ProtoIndexChanged(ref name, old, new) => {
+                match new {
+                    Some(new) => write!(f, "proto {} index changed from {} to {}", name, old, new),
+                    None => write!(f, "proto {} index {} removed", name, old),
+                }
+            }
+        }
+    }
+}
+
+/// compare the configs, the breaking changes come first, then sorted by the message
+pub fn check_compatible(old: &Config, new: &Config) -> Vec<Change> {
+    let mut changes = vec![];
+    check_fields("", old.get_fields(), new.get_fields(), &mut changes);
+    let empty = HashMap::new();
+    for (name, schema) in old.get_schemas() {
+        let new_fields = new.get_schema(name).map(|s| s.get_fields()).unwrap_or(&empty);
+        check_fields(&format!("{}.", name), schema.get_fields(), new_fields, &mut changes);
+    }
+    for (name, schema) in new.get_schemas() {
+        if old.get_schema(name).is_none() {
+            check_fields(&format!("{}.", name), &empty, schema.get_fields(), &mut changes);
+        }
+    }
+
+    for (name, values) in old.get_enums() {
+        for (value_name, value) in values.get_values() {
+            let qualified = format!("{}.{}", name, value_name);
+            match new.get_enum(name).and_then(|e| e.get_value(value_name)) {
+                Some(new_value) if new_value != *value => {
+                    changes.push(Change::EnumValueChanged(qualified, *value, new_value))
+                }
+                Some(_) => (),
+                None => changes.push(Change::EnumValueRemoved(qualified)),
+            }
+        }
+    }
+
+    for (name, proto) in old.get_protos() {
+        let new_proto = unwrap_or!(new.get_proto_by_name(name), {
+            changes.push(Change::ProtoRemoved(name.clone()));
+            continue;
+        });
+        if new_proto.args != proto.args {
+            changes.push(Change::ProtoArgsChanged(name.clone(),
+                                                  proto.args.clone(),
+                                                  new_proto.args.clone()));
+        }
+        if let Some(index) = proto.index {
+            if new_proto.index != Some(index) {
+                changes.push(Change::ProtoIndexChanged(name.clone(), index, new_proto.index));
+            }
+        }
+    }
+    for name in new.get_protos().keys() {
+        if old.get_proto_by_name(name).is_none() {
+            changes.push(Change::ProtoAdded(name.clone()));
+        }
+    }
+    changes.sort_by_key(|c| (!c.is_breaking(), c.to_string()));
+    changes
+}
+
+/// compare the fields of the same namespace, the names are qualified by the prefix
+fn check_fields(prefix: &str,
+                old: &HashMap<String, Field>,
+                new: &HashMap<String, Field>,
+                changes: &mut Vec<Change>) {
+    fn find_index(fields: &HashMap<String, Field>, index: u16) -> Option<(&String, &Field)> {
+        fields.iter().find(|&(_, field)| field.index == index)
+    }
+    for (name, field) in old {
+        match find_index(new, field.index) {
+            Some((new_name, new_field)) => {
+                if new_name != name {
+                    changes.push(Change::FieldRenamed(field.index,
+                                                      format!("{}{}", prefix, name),
+                                                      format!("{}{}", prefix, new_name)));
+                }
+                if new_field.pattern != field.pattern {
+                    changes.push(Change::FieldPatternChanged(format!("{}{}", prefix, new_name),
+                                                             field.pattern.clone(),
+                                                             new_field.pattern.clone()));
+                }
+                if new_field.required != field.required {
+                    changes.push(Change::FieldRequiredChanged(format!("{}{}", prefix, new_name),
+                                                              field.required,
+                                                              new_field.required));
+                }
+            }
+            None => {
+                match new.get(name) {
+                    Some(new_field) => {
+                        changes.push(Change::FieldIndexChanged(format!("{}{}", prefix, name),
+                                                               field.index,
+                                                               new_field.index))
+                    }
+                    None => changes.push(Change::FieldRemoved(format!("{}{}", prefix, name))),
+                }
+            }
+        }
+    }
+    for (name, field) in new {
+        if find_index(old, field.index).is_none() && !old.contains_key(name) {
+            changes.push(Change::FieldAdded(format!("{}{}", prefix, name)));
+            if field.required {
+                changes.push(Change::FieldRequiredChanged(format!("{}{}", prefix, name),
+                                                          false,
+                                                          true));
+            }
+        }
+    }
+}
@@ -1,24 +1,226 @@
-use rustc_serialize::json;
-use std::collections::HashMap;
+use rustc_serialize::json::{self, Json, ParserError, ToJson};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
 
-#[derive(RustcDecodable, Debug)]
+use {Value, RpResult, RpError, ErrorKind, DEFAULT_FLOAT_SCALE, TYPE_NIL, TYPE_STEP};
+use {TYPE_U8, TYPE_I8, TYPE_U16, TYPE_I16, TYPE_U32, TYPE_I32, TYPE_U64, TYPE_I64, TYPE_FLOAT,
+     TYPE_F32, TYPE_F64, TYPE_BOOL, TYPE_STR, TYPE_RAW, TYPE_ASTR, TYPE_LONG_STR,
+     TYPE_LONG_RAW};
+use {get_type_by_name, get_name_by_type, get_pattern_by_type, get_type_by_value,
+     get_array_contains_type, get_array_values, new_array_value, is_nested_array_type,
+     find_bad_array_element,
+     get_canonical_name, get_canonical_pattern, get_schema_name, get_enum_name,
+     get_optional_base};
+
+#[derive(Debug)]
 pub struct Field {
     pub index: u16,
     pub pattern: String,
+    /// the fixed-point scale of "float" values, use the config scale if none
+    pub scale: Option<u32>,
+    /// the map must contain the field if it has no default
+    pub required: bool,
+    /// the value decode_map fills when the field is absent
+    pub default: Option<Value>,
+    pub deprecated: bool,
+    /// empty if not declared
+    pub desc: String,
 }
 
-#[derive(RustcDecodable, Debug)]
+#[derive(Debug)]
 pub struct Proto {
+    /// empty if not declared
     pub msg_type: String,
     pub args: Vec<String>,
+    /// the name of each arg, empty if not named
+    pub arg_names: Vec<String>,
+    /// the index can be encoded instead of the proto name
+    pub index: Option<u16>,
+    /// sent in both directions if none
+    pub direction: Option<Direction>,
+    pub deprecated: bool,
+    /// empty if not declared
+    pub desc: String,
+}
+
+/// the direction the proto is sent
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Direction {
+    /// "c2s" in the protocol
+    ClientToServer,
+    /// "s2c" in the protocol
+    ServerToClient,
+}
+
+impl Direction {
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "c2s" => Some(Direction::ClientToServer),
+            "s2c" => Some(Direction::ServerToClient),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match *self {
+            Direction::ClientToServer => "c2s",
+            Direction::ServerToClient => "s2c",
+        }
+    }
+
+    pub fn reverse(&self) -> Direction {
+        match *self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        }
+    }
+}
+
+/// how to handle the map value whose type is not the field pattern
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum MismatchPolicy {
+    /// encode or decode the value as it is
+    Ignore,
+    /// keep the value and collect the mismatch into the `Report` of the call
+    Collect,
+    /// fail with TypeNotMatchError
+    Strict,
+}
+
+/// how encode_map handle the key which is not defined in field
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum UnknownKeyPolicy {
+    /// drop the key and value silently
+    Drop,
+    /// fail with MissingError
+    Error,
+    /// drop the key and value and collect the key into the `Report` of the call
+    Collect,
+}
+
+/// the fields of the map whose pattern is "map<name>"
+#[derive(Debug)]
+pub struct Schema {
+    field: HashMap<String, Field>,
+    index_field: HashMap<u16, String>,
+}
+
+/// the names and the u16 values of the field whose pattern is "enum<name>"
+#[derive(Debug)]
+pub struct Enum {
+    values: HashMap<String, u16>,
+    value_names: HashMap<u16, String>,
 }
 
 #[derive(Debug)]
 pub struct Config {
     field: HashMap<String, Field>,
+    schemas: HashMap<String, Schema>,
+    enums: HashMap<String, Enum>,
     proto: HashMap<String, Proto>,
     index_field: HashMap<u16, String>,
+    index_proto: HashMap<u16, String>,
     msg_proto: HashMap<String, String>,
+    float_scale: u32,
+    doc_float_scale: bool,
+    coerce_number: bool,
+    proto_by_index: bool,
+    send_direction: Option<Direction>,
+    types: Vec<String>,
+    mismatch_policy: MismatchPolicy,
+    unknown_key_policy: UnknownKeyPolicy,
+}
+
+/// the problems found by one encode or decode call, see `encode_map_collect`
+#[derive(Debug, Default)]
+pub struct Report {
+    /// the mismatches kept by MismatchPolicy::Collect
+    pub issues: Vec<String>,
+    /// the keys dropped by UnknownKeyPolicy::Collect
+    pub dropped_keys: Vec<String>,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report::default()
+    }
+}
+
+impl Schema {
+    pub fn new_by_map(field: HashMap<String, Field>) -> Schema {
+        let mut index_field: HashMap<u16, String> = HashMap::new();
+        for (name, f) in &field {
+            index_field.insert(f.index, name.clone());
+        }
+        Schema {
+            field: field,
+            index_field: index_field,
+        }
+    }
+
+    pub fn get_fields(&self) -> &HashMap<String, Field> {
+        &self.field
+    }
+
+    pub fn get_field_by_name(&self, name: &String) -> Option<&Field> {
+        self.field.get(name)
+    }
+
+    pub fn get_field_by_index(&self, index: &u16) -> Option<&Field> {
+        let name = unwrap_or!(self.get_field_index_name(index), return None);
+        self.field.get(name)
+    }
+
+    pub fn get_field_index_name(&self, index: &u16) -> Option<&String> {
+        self.index_field.get(index)
+    }
+}
+
+impl Enum {
+    pub fn new_by_map(values: HashMap<String, u16>) -> Enum {
+        let mut value_names: HashMap<u16, String> = HashMap::new();
+        for (name, value) in &values {
+            value_names.insert(*value, name.clone());
+        }
+        Enum {
+            values: values,
+            value_names: value_names,
+        }
+    }
+
+    pub fn get_values(&self) -> &HashMap<String, u16> {
+        &self.values
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<u16> {
+        self.values.get(name).map(|v| *v)
+    }
+
+    pub fn get_value_name(&self, value: &u16) -> Option<&String> {
+        self.value_names.get(value)
+    }
+}
+
+impl ToJson for Enum {
+    fn to_json(&self) -> Json {
+        let values: BTreeMap<String, Json> = self.values
+            .iter()
+            .map(|(name, v)| (name.clone(), v.to_json()))
+            .collect();
+        Json::Object(values)
+    }
+}
+
+impl ToJson for Schema {
+    fn to_json(&self) -> Json {
+        let field: BTreeMap<String, Json> = self.field
+            .iter()
+            .map(|(name, f)| (name.clone(), f.to_json()))
+            .collect();
+        Json::Object(field)
+    }
 }
 
 impl Field {
@@ -26,6 +228,11 @@ impl Field {
         Field {
             index: 0,
             pattern: "nil".to_string(),
+            scale: None,
+            required: false,
+            default: None,
+            deprecated: false,
+            desc: String::new(),
         }
     }
 
@@ -37,62 +244,605 @@ impl Field {
         Field {
             index: 0,
             pattern: pattern,
+            scale: None,
+            required: false,
+            default: None,
+            deprecated: false,
+            desc: String::new(),
+        }
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated
+    }
+
+    pub fn get_desc(&self) -> Option<&str> {
+        if self.desc.is_empty() {
+            None
+        } else {
+            Some(&self.desc)
         }
     }
 }
 
+impl ToJson for Field {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        object.insert("index".to_string(), self.index.to_json());
+        object.insert("pattern".to_string(), self.pattern.to_json());
+        if let Some(scale) = self.scale {
+            object.insert("scale".to_string(), scale.to_json());
+        }
+        if self.required {
+            object.insert("required".to_string(), true.to_json());
+        }
+        if let Some(ref default) = self.default {
+            object.insert("default".to_string(), value_to_json(default));
+        }
+        insert_meta_json(&mut object, self.deprecated, &self.desc);
+        Json::Object(object)
+    }
+}
+
+impl ToJson for Proto {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        if let Some(index) = self.index {
+            object.insert("index".to_string(), index.to_json());
+        }
+        if !self.msg_type.is_empty() {
+            object.insert("msg_type".to_string(), self.msg_type.to_json());
+        }
+        if let Some(direction) = self.direction {
+            object.insert("direction".to_string(), direction.get_name().to_json());
+        }
+        insert_meta_json(&mut object, self.deprecated, &self.desc);
+        let args = self.args
+            .iter()
+            .zip(&self.arg_names)
+            .map(|(pattern, arg_name)| if arg_name.is_empty() {
+                pattern.to_json()
+            } else {
+                let mut arg = BTreeMap::new();
+                arg.insert("name".to_string(), arg_name.to_json());
+                arg.insert("type".to_string(), pattern.to_json());
+                Json::Object(arg)
+            })
+            .collect();
+        object.insert("args".to_string(), Json::Array(args));
+        Json::Object(object)
+    }
+}
+
+impl Proto {
+    pub fn get_direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated
+    }
+
+    pub fn get_desc(&self) -> Option<&str> {
+        if self.desc.is_empty() {
+            None
+        } else {
+            Some(&self.desc)
+        }
+    }
+
+    /// the position of the named arg
+    pub fn get_arg_index(&self, arg_name: &str) -> Option<usize> {
+        self.arg_names.iter().position(|n| !n.is_empty() && n == arg_name)
+    }
+
+    /// the args num without the trailing optional args
+    pub fn get_min_args(&self) -> usize {
+        self.args.len() -
+        self.args.iter().rev().take_while(|arg| get_optional_base(arg).is_some()).count()
+    }
+
+    /// the value must be the type declared by the pos arg, and so is every element of array
+    pub fn check_arg(&self, name: &str, pos: usize, value: &Value) -> RpResult<()> {
+        let pattern = unwrap_or!(self.args.get(pos), {
+            fail!((ErrorKind::TypeNotMatchError,
+                   "the data num not match protocol args num",
+                   format!("proto {} has no arg {}", name, pos)))
+        });
+        if *value == Value::Nil && get_optional_base(pattern).is_some() {
+            return Ok(());
+        }
+        if let Some(t) = find_bad_array_element(value) {
+            fail!((ErrorKind::TypeNotMatchError,
+                   "the data type not match protocol args",
+                   format!("proto {} arg {} array of {} is not the array of arrays",
+                           name,
+                           pos,
+                           get_pattern_by_type(t))));
+        }
+        let t = get_type_by_value(value);
+        if t != get_type_by_name(pattern) {
+            fail!((ErrorKind::TypeNotMatchError,
+                   "the data type not match protocol args",
+                   format!("proto {} arg {} expect {} but {}",
+                           name,
+                           pos,
+                           pattern,
+                           get_pattern_by_type(t))));
+        }
+        let mut path = vec![];
+        if let Some((must_type, sub_type)) = find_element_mismatch(value, &mut path) {
+            let path: Vec<String> = path.iter().map(|j| j.to_string()).collect();
+            fail!((ErrorKind::TypeNotMatchError,
+                   "the data type not match protocol args",
+                   format!("proto {} arg {} element {} expect {} but {}",
+                           name,
+                           pos,
+                           path.join("."),
+                           get_pattern_by_type(must_type),
+                           get_pattern_by_type(sub_type))));
+        }
+        Ok(())
+    }
+}
+
+/// the (expect, actual) type of the first element not match the array at any level,
+/// the path is the position of the element in each level
+fn find_element_mismatch(value: &Value, path: &mut Vec<usize>) -> Option<(u16, u16)> {
+    let val = unwrap_or!(get_array_values(value), return None);
+    let must_type = get_array_contains_type(value);
+    for (j, v) in val.iter().enumerate() {
+        path.push(j);
+        let sub_type = get_type_by_value(v);
+        if sub_type != must_type {
+            return Some((must_type, sub_type));
+        }
+        if let Some(mismatch) = find_element_mismatch(v, path) {
+            return Some(mismatch);
+        }
+        path.pop();
+    }
+    None
+}
+
 impl Config {
     pub fn new_empty() -> Config {
         Config {
             field: HashMap::new(),
+            schemas: HashMap::new(),
+            enums: HashMap::new(),
             proto: HashMap::new(),
             index_field: HashMap::new(),
+            index_proto: HashMap::new(),
             msg_proto: HashMap::new(),
+            float_scale: DEFAULT_FLOAT_SCALE,
+            doc_float_scale: false,
+            coerce_number: false,
+            proto_by_index: false,
+            send_direction: None,
+            types: vec![],
+            mismatch_policy: MismatchPolicy::Ignore,
+            unknown_key_policy: UnknownKeyPolicy::Drop,
         }
     }
     pub fn new_by_map(field: HashMap<String, Field>, proto: HashMap<String, Proto>) -> Config {
         let mut index_field: HashMap<u16, String> = HashMap::new();
+        let mut index_proto: HashMap<u16, String> = HashMap::new();
         let mut msg_proto: HashMap<String, String> = HashMap::new();
         for (name, f) in &field {
             index_field.insert(f.index, name.clone());
         }
         for (name, p) in &proto {
-            msg_proto.insert(name.clone(), p.msg_type.clone());
+            if let Some(index) = p.index {
+                index_proto.insert(index, name.clone());
+            }
+            if !p.msg_type.is_empty() {
+                msg_proto.insert(name.clone(), p.msg_type.clone());
+            }
         }
         Config {
             field: field,
+            schemas: HashMap::new(),
+            enums: HashMap::new(),
             proto: proto,
             index_field: index_field,
+            index_proto: index_proto,
             msg_proto: msg_proto,
+            float_scale: DEFAULT_FLOAT_SCALE,
+            doc_float_scale: false,
+            coerce_number: false,
+            proto_by_index: false,
+            send_direction: None,
+            types: vec![],
+            mismatch_policy: MismatchPolicy::Ignore,
+            unknown_key_policy: UnknownKeyPolicy::Drop,
+        }
+    }
+
+    /// parse the whole protocol document, which contains "field" and "proto"
+    pub fn try_new_by_full_str(config: &str) -> RpResult<Config> {
+        Self::new_by_document(try!(parse_document(config, true)))
+    }
+
+    fn new_by_document(doc: Document) -> RpResult<Config> {
+        let mut config = Self::new_by_map(doc.field, doc.proto);
+        for (name, field) in doc.schemas {
+            config.add_schema(name, field);
+        }
+        for (name, values) in doc.enums {
+            config.add_enum(name, values);
+        }
+        config.types = doc.types;
+        if let Some(scale) = doc.float_scale {
+            try!(config.set_float_scale(scale));
+            config.doc_float_scale = true;
+        }
+        try!(config.check_validate());
+        Ok(config)
+    }
+
+    /// read the whole protocol document from the reader
+    pub fn from_reader<R: Read>(mut reader: R) -> RpResult<Config> {
+        let mut config = String::new();
+        try!(reader.read_to_string(&mut config));
+        Self::try_new_by_full_str(&config)
+    }
+
+    /// read the whole protocol document from the file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> RpResult<Config> {
+        let path = path.as_ref();
+        File::open(path)
+            .map_err(RpError::from)
+            .and_then(Self::from_reader)
+            .map_err(|err| with_file_name(path, err))
+    }
+
+    /// merge the protocol files, each file may only contain some of the sections,
+    /// the same field or proto name or index in different files is an error
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> RpResult<Config> {
+        let mut merged = Document::new();
+        let mut origins: HashMap<String, &Path> = HashMap::new();
+        for path in paths {
+            let path = path.as_ref();
+            let doc = try!(read_document(path).map_err(|err| with_file_name(path, err)));
+            try!(merge_document(&mut merged, &mut origins, path, doc));
         }
+        Self::new_by_document(merged)
+    }
+
+    /// merge all the protocol files in the dir sorted by file name, only the ".json" and ".txt"
+    /// files are protocol files, the hidden files and others such as "README.md" are skipped
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> RpResult<Config> {
+        let dir = dir.as_ref();
+        let mut paths = vec![];
+        for entry in try!(fs::read_dir(dir).map_err(|err| with_file_name(dir, err.into()))) {
+            let path = try!(entry.map_err(|err| with_file_name(dir, err.into()))).path();
+            let hidden = path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with("."))
+                .unwrap_or(true);
+            let protocol = path.extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e == "json" || e == "txt")
+                .unwrap_or(false);
+            if path.is_file() && !hidden && protocol {
+                paths.push(path);
+            }
+        }
+        ensure!(!paths.is_empty(),
+                (ErrorKind::ConfigError,
+                 "missing protocol file",
+                 format!("no file in {}", dir.display())));
+        paths.sort();
+        Self::from_files(&paths)
     }
 
     pub fn new_by_full_str(config: &str) -> Option<Config> {
-        let info = json::Json::from_str(config);
-        if info.is_err() {
-            return None;
+        Self::try_new_by_full_str(config).ok()
+    }
+
+    /// parse the "field" and "proto" sections separately
+    pub fn try_new(field: &str, proto: &str) -> RpResult<Config> {
+        let field = try!(parse_fields(&try!(parse_json(field))));
+        let proto = try!(parse_protos(&try!(parse_json(proto))));
+        let config = Self::new_by_map(field, proto);
+        try!(config.check_validate());
+        Ok(config)
+    }
+
+    /// report all the problems of the schema, such as duplicate indices,
+    /// unknown patterns and the reserved field index 0
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        self.validate_fields("field", &self.field, &mut problems);
+        // the global fields are shared by every plain map, so they can't be required
+        let mut field_names: Vec<&String> = self.field.keys().collect();
+        field_names.sort();
+        for name in field_names {
+            let field = &self.field[name];
+            if field.required || field.default.is_some() {
+                problems.push(format!("field {} required or default is only for schema fields",
+                                      name));
+            }
         }
-        let info = info.ok().unwrap();
-        let field = info.find("field");
-        let proto = info.find("proto");
-        if field.is_none() || proto.is_none() {
-            return None;
+        let mut schema_names: Vec<&String> = self.schemas.keys().collect();
+        schema_names.sort();
+        for name in schema_names {
+            self.validate_fields(&format!("schema {} field", name),
+                                 &self.schemas[name].field,
+                                 &mut problems);
         }
-        let field: Result<HashMap<String, Field>, _> = json::decode(&field.unwrap().to_string());
-        let proto: Result<HashMap<String, Proto>, _> = json::decode(&proto.unwrap().to_string());
-        if field.is_err() || proto.is_err() {
-            return None;
+        let mut enum_names: Vec<&String> = self.enums.keys().collect();
+        enum_names.sort();
+        for name in enum_names {
+            let mut value_names: Vec<&String> = self.enums[name].values.keys().collect();
+            value_names.sort();
+            let mut values: HashMap<u16, &String> = HashMap::new();
+            for value_name in value_names {
+                let value = self.enums[name].values[value_name];
+                if let Some(other) = values.get(&value) {
+                    problems.push(format!("enum {} names {} and {} share value {}",
+                                          name,
+                                          other,
+                                          value_name,
+                                          value));
+                    continue;
+                }
+                values.insert(value, value_name);
+            }
+        }
+
+        let mut proto_names: Vec<&String> = self.proto.keys().collect();
+        proto_names.sort();
+        let mut index_proto: HashMap<u16, &String> = HashMap::new();
+        for name in proto_names {
+            let proto = &self.proto[name];
+            // the empty name on the wire marks the proto index follow
+            if name.is_empty() {
+                problems.push("proto name can't be empty".to_string());
+            }
+            if let Some(index) = proto.index {
+                if let Some(other) = index_proto.get(&index) {
+                    problems.push(format!("proto {} and {} share index {}", other, name, index));
+                } else {
+                    index_proto.insert(index, name);
+                }
+            }
+            for (i, arg) in proto.args.iter().enumerate() {
+                if let Some(problem) = self.check_pattern(arg) {
+                    problems.push(format!("proto {} arg {} pattern {} {}", name, i, arg, problem));
+                }
+                let arg_name = &proto.arg_names[i];
+                if !arg_name.is_empty() && proto.get_arg_index(arg_name) != Some(i) {
+                    problems.push(format!("proto {} arg name {} is duplicated", name, arg_name));
+                }
+            }
+        }
+        problems
+    }
+
+    fn validate_fields(&self,
+                       prefix: &str,
+                       fields: &HashMap<String, Field>,
+                       problems: &mut Vec<String>) {
+        let mut field_names: Vec<&String> = fields.keys().collect();
+        field_names.sort();
+        let mut index_field: HashMap<u16, &String> = HashMap::new();
+        for name in field_names {
+            let field = &fields[name];
+            if field.index == 0 {
+                problems.push(format!("{} {} index 0 is reserved", prefix, name));
+            } else if let Some(other) = index_field.get(&field.index) {
+                problems.push(format!("{} {} and {} share index {}",
+                                      prefix,
+                                      other,
+                                      name,
+                                      field.index));
+            } else {
+                index_field.insert(field.index, name);
+            }
+            if let Some(problem) = self.check_pattern(&field.pattern) {
+                problems.push(format!("{} {} pattern {} {}", prefix, name, field.pattern, problem));
+            } else if get_optional_base(&field.pattern).is_some() {
+                problems.push(format!("{} {} pattern {} is optional only in proto args",
+                                      prefix,
+                                      name,
+                                      field.pattern));
+            } else if let (Some(enum_name), Some(default)) = (get_enum_name(&field.pattern),
+                                                              field.default.as_ref()) {
+                if self.encode_enum(enum_name, default, &mut Report::new()).is_err() {
+                    problems.push(format!("{} {} default {:?} is not in enum {}",
+                                          prefix,
+                                          name,
+                                          default,
+                                          enum_name));
+                }
+            }
         }
-        Some(Self::new_by_map(field.ok().unwrap(), proto.ok().unwrap()))
+    }
+
+    /// the problem of the pattern, None if it is vailed
+    fn check_pattern(&self, pattern: &str) -> Option<&'static str> {
+        let t = get_type_by_name(pattern);
+        if t == TYPE_NIL {
+            return Some("is unknown");
+        }
+        if t == TYPE_LONG_STR || t == TYPE_LONG_RAW {
+            return Some("is only for the wire");
+        }
+        // the array of arrays is in types if its innermost array is
+        let mut t = t;
+        while is_nested_array_type(t) {
+            t -= TYPE_STEP;
+        }
+        let name = get_name_by_type(t);
+        if !self.types.is_empty() && !self.types.iter().any(|t| t == name) {
+            return Some("is not in types");
+        }
+        match get_schema_name(pattern) {
+            Some(schema) if !self.schemas.contains_key(schema) => {
+                return Some("refers unknown schema")
+            }
+            _ => (),
+        }
+        match get_enum_name(pattern) {
+            Some(name) if !self.enums.contains_key(name) => Some("refers unknown enum"),
+            _ => None,
+        }
+    }
+
+    fn check_validate(&self) -> RpResult<()> {
+        let problems = self.validate();
+        if !problems.is_empty() {
+            fail!((ErrorKind::ConfigError, "invalid schema", problems.join("; ")));
+        }
+        Ok(())
     }
 
     pub fn new(field: &str, proto: &str) -> Option<Config> {
-        let field: Result<HashMap<String, Field>, _> = json::decode(field);
-        let proto: Result<HashMap<String, Proto>, _> = json::decode(proto);
-        if field.is_err() || proto.is_err() {
-            return None;
+        Self::try_new(field, proto).ok()
+    }
+
+    /// the protocol document of the config, keys are sorted so the output is stable
+    pub fn to_json_string(&self) -> String {
+        format!("{}", self.to_json().pretty())
+    }
+
+    pub fn get_fields(&self) -> &HashMap<String, Field> {
+        &self.field
+    }
+
+    pub fn get_protos(&self) -> &HashMap<String, Proto> {
+        &self.proto
+    }
+
+    /// add the fields of the map whose pattern is "map<name>", replace the old one if exists
+    pub fn add_schema(&mut self, name: String, field: HashMap<String, Field>) {
+        self.schemas.insert(name, Schema::new_by_map(field));
+    }
+
+    pub fn get_schema(&self, name: &str) -> Option<&Schema> {
+        self.schemas.get(name)
+    }
+
+    pub fn get_schemas(&self) -> &HashMap<String, Schema> {
+        &self.schemas
+    }
+
+    /// add or replace the enum used by the pattern "enum<name>"
+    pub fn add_enum(&mut self, name: String, values: HashMap<String, u16>) {
+        self.enums.insert(name, Enum::new_by_map(values));
+    }
+
+    pub fn get_enum(&self, name: &str) -> Option<&Enum> {
+        self.enums.get(name)
+    }
+
+    pub fn get_enums(&self) -> &HashMap<String, Enum> {
+        &self.enums
+    }
+
+    /// convert the enum names in the value to the u16 values, the unknown name fails with
+    /// TypeNotMatchError, the unknown u16 value is handled by the mismatch policy
+    pub fn encode_enum(&self, name: &str, value: &Value, report: &mut Report) -> RpResult<Value> {
+        let values = try!(self.get_enum_or_fail(name));
+        match *value {
+            Value::Str(ref s) => {
+                let v = unwrap_or!(values.get_value(s), {
+                    fail!((ErrorKind::TypeNotMatchError,
+                           "the data not in the enum",
+                           format!("enum {} has no name {}", name, s)))
+                });
+                Ok(Value::U16(v))
+            }
+            Value::U16(v) => {
+                if values.get_value_name(&v).is_none() {
+                    try!(self.enum_mismatch(format!("enum {} has no value {}", name, v), report));
+                }
+                Ok(Value::U16(v))
+            }
+            Value::AStr(ref val) | Value::AU16(ref val) => {
+                let mut array = vec![];
+                for v in val {
+                    array.push(try!(self.encode_enum(name, v, report)));
+                }
+                Ok(Value::AU16(array))
+            }
+            Value::AArray(t, ref val) => {
+                let mut array = vec![];
+                for v in val {
+                    array.push(try!(self.encode_enum(name, v, report)));
+                }
+                Ok(Value::AArray(replace_base_type(t, TYPE_STR, TYPE_U16), array))
+            }
+            _ => Ok(value.clone()),
         }
-        Some(Self::new_by_map(field.ok().unwrap(), proto.ok().unwrap()))
+    }
+
+    /// convert the u16 values in the value to the enum names, the unknown u16 value is kept
+    /// as number and handled by the mismatch policy
+    pub fn decode_enum(&self, name: &str, value: Value, report: &mut Report) -> RpResult<Value> {
+        let values = try!(self.get_enum_or_fail(name));
+        match value {
+            Value::U16(v) => {
+                match values.get_value_name(&v) {
+                    Some(s) => Ok(Value::Str(s.clone())),
+                    None => {
+                        let detail = format!("enum {} has no value {}", name, v);
+                        try!(self.enum_mismatch(detail, report));
+                        Ok(value)
+                    }
+                }
+            }
+            Value::AU16(val) => {
+                let mut array = vec![];
+                for v in &val {
+                    match *v {
+                        Value::U16(v) if values.get_value_name(&v).is_some() => {
+                            array.push(Value::Str(values.get_value_name(&v).unwrap().clone()));
+                        }
+                        _ => {
+                            let detail = format!("enum {} has no value {:?}", name, v);
+                            try!(self.enum_mismatch(detail, report));
+                            return Ok(Value::AU16(val));
+                        }
+                    }
+                }
+                Ok(Value::AStr(array))
+            }
+            Value::AArray(t, val) => {
+                let mut array = vec![];
+                for v in &val {
+                    let v = try!(self.decode_enum(name, v.clone(), report));
+                    // keep the whole array as number if any value is unknown
+                    if get_type_by_value(&v) % TYPE_STEP != TYPE_STR {
+                        return Ok(Value::AArray(t, val));
+                    }
+                    array.push(v);
+                }
+                Ok(Value::AArray(replace_base_type(t, TYPE_U16, TYPE_STR), array))
+            }
+            _ => Ok(value),
+        }
+    }
+
+    fn get_enum_or_fail(&self, name: &str) -> RpResult<&Enum> {
+        Ok(unwrap_or!(self.get_enum(name), {
+            fail!((ErrorKind::MissingError,
+                   "missing the enum",
+                   format!("unknown enum {}", name)))
+        }))
+    }
+
+    fn enum_mismatch(&self, detail: String, report: &mut Report) -> RpResult<()> {
+        match self.mismatch_policy {
+            MismatchPolicy::Ignore => (),
+            MismatchPolicy::Collect => report.issues.push(detail),
+            MismatchPolicy::Strict => {
+                fail!((ErrorKind::TypeNotMatchError, "the data not in the enum", detail))
+            }
+        }
+        Ok(())
     }
 
     pub fn get_field_by_name(&self, name: &String) -> Option<&Field> {
@@ -112,7 +862,681 @@ impl Config {
         self.index_field.get(index)
     }
 
+    pub fn get_proto_by_index(&self, index: &u16) -> Option<&Proto> {
+        let name = unwrap_or!(self.get_proto_index_name(index), return None);
+        self.proto.get(name)
+    }
+
+    pub fn get_proto_index_name(&self, index: &u16) -> Option<&String> {
+        self.index_proto.get(index)
+    }
+
     pub fn get_proto_msg_type(&self, name: &String) -> Option<&String> {
         self.msg_proto.get(name)
     }
+
+    /// the type names declared by the "types" section, empty if not declared
+    pub fn get_types(&self) -> &Vec<String> {
+        &self.types
+    }
+
+    /// set the default fixed-point scale of "float" values, the zero scale fails with ConfigError
+    pub fn set_float_scale(&mut self, scale: u32) -> RpResult<()> {
+        ensure!(scale > 0,
+                (ErrorKind::ConfigError,
+                 "bad float_scale",
+                 format!("float_scale must be u32 above zero but {}", scale)));
+        self.float_scale = scale;
+        Ok(())
+    }
+
+    pub fn get_float_scale(&self) -> u32 {
+        self.float_scale
+    }
+
+    /// decode_proto convert the received number to the wider declared number type,
+    /// such as u8 received where u16 declared, default is false
+    pub fn set_coerce_number(&mut self, coerce: bool) {
+        self.coerce_number = coerce;
+    }
+
+    pub fn is_coerce_number(&self) -> bool {
+        self.coerce_number
+    }
+
+    /// encode_proto write the proto index instead of the name if the proto has index,
+    /// decode_proto can read both, default is false
+    pub fn set_proto_by_index(&mut self, by_index: bool) {
+        self.proto_by_index = by_index;
+    }
+
+    pub fn is_proto_by_index(&self) -> bool {
+        self.proto_by_index
+    }
+
+    /// the direction of the protos this side sends, encode_proto reject the proto declared
+    /// in the other direction and so does decode_proto, default is none and not checked
+    pub fn set_send_direction(&mut self, direction: Option<Direction>) {
+        self.send_direction = direction;
+    }
+
+    pub fn get_send_direction(&self) -> Option<Direction> {
+        self.send_direction
+    }
+
+    /// fail with DirectionError if the proto is not sent or received by this side
+    pub fn check_proto_direction(&self, name: &str, proto: &Proto, sending: bool) -> RpResult<()> {
+        let send_direction = unwrap_or!(self.send_direction, return Ok(()));
+        let direction = unwrap_or!(proto.direction, return Ok(()));
+        let expect = if sending {
+            send_direction
+        } else {
+            send_direction.reverse()
+        };
+        if direction != expect {
+            fail!((ErrorKind::DirectionError,
+                   "the proto is in the wrong direction",
+                   format!("proto {} is {} but this side {} {}",
+                           name,
+                           direction.get_name(),
+                           if sending { "sends" } else { "receives" },
+                           expect.get_name())));
+        }
+        Ok(())
+    }
+
+    /// copy the settings which are not from the protocol document, such as the policies,
+    /// the float_scale is copied unless this document declares it
+    pub fn copy_settings(&mut self, other: &Config) {
+        if !self.doc_float_scale {
+            self.float_scale = other.float_scale;
+        }
+        self.coerce_number = other.coerce_number;
+        self.proto_by_index = other.proto_by_index;
+        self.mismatch_policy = other.mismatch_policy;
+        self.unknown_key_policy = other.unknown_key_policy;
+        self.send_direction = other.send_direction;
+    }
+
+    /// the scale declared by the field, or the config default
+    pub fn get_field_float_scale(&self, field: Option<&Field>) -> u32 {
+        field.and_then(|f| f.scale).unwrap_or(self.float_scale)
+    }
+
+    /// set how encode_map and decode_map handle the value not match the field pattern,
+    /// default is MismatchPolicy::Ignore
+    pub fn set_mismatch_policy(&mut self, policy: MismatchPolicy) {
+        self.mismatch_policy = policy;
+    }
+
+    pub fn get_mismatch_policy(&self) -> MismatchPolicy {
+        self.mismatch_policy
+    }
+
+    /// check the map value with the field pattern according to the mismatch policy
+    pub fn check_field_value(&self,
+                             name: &str,
+                             field: &Field,
+                             value: &Value,
+                             report: &mut Report)
+                             -> RpResult<()> {
+        let t = get_type_by_value(value);
+        if self.mismatch_policy == MismatchPolicy::Ignore || t == get_type_by_name(&field.pattern) {
+            return Ok(());
+        }
+        let detail = format!("field {} expect {} but {}",
+                             name,
+                             field.pattern,
+                             get_pattern_by_type(t));
+        if self.mismatch_policy == MismatchPolicy::Strict {
+            fail!((ErrorKind::TypeNotMatchError, "the data type not match field pattern", detail));
+        }
+        report.issues.push(detail);
+        Ok(())
+    }
+
+    /// set how encode_map handle the key not defined in field, default is UnknownKeyPolicy::Drop
+    pub fn set_unknown_key_policy(&mut self, policy: UnknownKeyPolicy) {
+        self.unknown_key_policy = policy;
+    }
+
+    pub fn get_unknown_key_policy(&self) -> UnknownKeyPolicy {
+        self.unknown_key_policy
+    }
+
+    /// handle the unknown key according to the unknown key policy
+    pub fn drop_unknown_key(&self, name: &str, report: &mut Report) -> RpResult<()> {
+        match self.unknown_key_policy {
+            UnknownKeyPolicy::Drop => (),
+            UnknownKeyPolicy::Error => {
+                fail!((ErrorKind::MissingError,
+                       "missing the field define",
+                       format!("unknown field {}", name)))
+            }
+            UnknownKeyPolicy::Collect => report.dropped_keys.push(name.to_string()),
+        }
+        Ok(())
+    }
+}
+
+/// replace the base type of the array type, such as u16[][] to str[][]
+fn replace_base_type(t: u16, from: u16, to: u16) -> u16 {
+    if t % TYPE_STEP == from { t - from + to } else { t }
+}
+
+/// fail with MissingError naming the first absent required field which has no default
+pub fn check_required_fields(fields: &HashMap<String, Field>,
+                             map: &HashMap<String, Value>)
+                             -> RpResult<()> {
+    let missing = fields.iter()
+        .filter(|&(name, field)| {
+            field.required && field.default.is_none() && !map.contains_key(name)
+        })
+        .map(|(name, _)| name)
+        .min();
+    if let Some(name) = missing {
+        fail!((ErrorKind::MissingError,
+               "missing the required field",
+               format!("field {} is required", name)));
+    }
+    Ok(())
+}
+
+impl ToJson for Config {
+    fn to_json(&self) -> Json {
+        let mut object = BTreeMap::new();
+        if !self.types.is_empty() {
+            object.insert("types".to_string(), self.types.to_json());
+        }
+        if self.float_scale != DEFAULT_FLOAT_SCALE {
+            object.insert("float_scale".to_string(), self.float_scale.to_json());
+        }
+        if !self.enums.is_empty() {
+            let enums: BTreeMap<String, Json> = self.enums
+                .iter()
+                .map(|(name, e)| (name.clone(), e.to_json()))
+                .collect();
+            object.insert("enums".to_string(), Json::Object(enums));
+        }
+        if !self.schemas.is_empty() {
+            let schemas: BTreeMap<String, Json> = self.schemas
+                .iter()
+                .map(|(name, s)| (name.clone(), s.to_json()))
+                .collect();
+            object.insert("schemas".to_string(), Json::Object(schemas));
+        }
+        let field: BTreeMap<String, Json> = self.field
+            .iter()
+            .map(|(name, f)| (name.clone(), f.to_json()))
+            .collect();
+        object.insert("field".to_string(), Json::Object(field));
+        let proto: BTreeMap<String, Json> = self.proto
+            .iter()
+            .map(|(name, p)| (name.clone(), p.to_json()))
+            .collect();
+        object.insert("proto".to_string(), Json::Object(proto));
+        Json::Object(object)
+    }
+}
+
+/// the sections of one protocol document
+struct Document {
+    field: HashMap<String, Field>,
+    schemas: HashMap<String, HashMap<String, Field>>,
+    enums: HashMap<String, HashMap<String, u16>>,
+    proto: HashMap<String, Proto>,
+    types: Vec<String>,
+    float_scale: Option<u32>,
+}
+
+impl Document {
+    fn new() -> Document {
+        Document {
+            field: HashMap::new(),
+            schemas: HashMap::new(),
+            enums: HashMap::new(),
+            proto: HashMap::new(),
+            types: vec![],
+            float_scale: None,
+        }
+    }
+}
+
+/// parse the document, "field" and "proto" sections must exist if require_all
+fn parse_document(config: &str, require_all: bool) -> RpResult<Document> {
+    let info = try!(parse_json(config));
+    ensure!(info.is_object(),
+            (ErrorKind::ConfigError, "bad document", "document must be object".to_string()));
+    let mut doc = Document::new();
+    match info.find("field") {
+        Some(field) => doc.field = try!(parse_fields(field)),
+        None if require_all => {
+            fail!((ErrorKind::ConfigError, "missing section", "field".to_string()))
+        }
+        None => (),
+    }
+    match info.find("proto") {
+        Some(proto) => doc.proto = try!(parse_protos(proto)),
+        None if require_all => {
+            fail!((ErrorKind::ConfigError, "missing section", "proto".to_string()))
+        }
+        None => (),
+    }
+    if let Some(schemas) = info.find("schemas") {
+        let object = unwrap_or!(schemas.as_object(), {
+            fail!((ErrorKind::ConfigError, "bad section", "schemas must be object".to_string()))
+        });
+        for (name, value) in object {
+            doc.schemas.insert(name.clone(), try!(parse_fields(value)));
+        }
+    }
+    if let Some(enums) = info.find("enums") {
+        let object = unwrap_or!(enums.as_object(), {
+            fail!((ErrorKind::ConfigError, "bad section", "enums must be object".to_string()))
+        });
+        for (name, value) in object {
+            doc.enums.insert(name.clone(), try!(parse_enum(name, value)));
+        }
+    }
+    if let Some(types) = info.find("types") {
+        doc.types = try!(parse_types(types));
+    }
+    if let Some(scale) = info.find("float_scale") {
+        match scale.as_u64() {
+            Some(s) if s > 0 && s <= u32::max_value() as u64 => doc.float_scale = Some(s as u32),
+            _ => {
+                fail!((ErrorKind::ConfigError,
+                       "bad float_scale",
+                       format!("float_scale must be u32 above zero but {}", scale)))
+            }
+        }
+    }
+    Ok(doc)
+}
+
+fn read_document(path: &Path) -> RpResult<Document> {
+    let mut config = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut config));
+    parse_document(&config, false)
+}
+
+fn with_file_name(path: &Path, err: RpError) -> RpError {
+    From::from((err.kind(), "load protocol file failed", format!("{}: {}", path.display(), err)))
+}
+
+/// merge the doc of the path into merged, the origins record where the names and indices from
+fn merge_document<'a>(merged: &mut Document,
+                      origins: &mut HashMap<String, &'a Path>,
+                      path: &'a Path,
+                      doc: Document)
+                      -> RpResult<()> {
+    {
+        let mut check = |key: String, what: String| -> RpResult<()> {
+            if let Some(other) = origins.get(&key).filter(|other| **other != path) {
+                fail!((ErrorKind::ConfigError,
+                       "conflict between files",
+                       format!("{} in {} and {}", what, other.display(), path.display())));
+            }
+            origins.insert(key, path);
+            Ok(())
+        };
+        let mut names: Vec<&String> = doc.field.keys().collect();
+        names.sort();
+        for name in names {
+            let index = doc.field[name].index;
+            try!(check(format!("field {}", name), format!("field {} defined", name)));
+            try!(check(format!("field index {}", index),
+                       format!("field index {} used", index)));
+        }
+        let mut names: Vec<&String> = doc.schemas.keys().collect();
+        names.sort();
+        for name in names {
+            try!(check(format!("schema {}", name), format!("schema {} defined", name)));
+        }
+        let mut names: Vec<&String> = doc.enums.keys().collect();
+        names.sort();
+        for name in names {
+            try!(check(format!("enum {}", name), format!("enum {} defined", name)));
+        }
+        let mut names: Vec<&String> = doc.proto.keys().collect();
+        names.sort();
+        for name in names {
+            try!(check(format!("proto {}", name), format!("proto {} defined", name)));
+            if let Some(index) = doc.proto[name].index {
+                try!(check(format!("proto index {}", index),
+                           format!("proto index {} used", index)));
+            }
+        }
+    }
+    match (merged.float_scale, doc.float_scale) {
+        (Some(a), Some(b)) if a != b => {
+            fail!((ErrorKind::ConfigError,
+                   "conflict between files",
+                   format!("float_scale {} and {} in {}", a, b, path.display())))
+        }
+        (None, Some(b)) => merged.float_scale = Some(b),
+        _ => (),
+    }
+    for t in doc.types {
+        if !merged.types.contains(&t) {
+            merged.types.push(t);
+        }
+    }
+    merged.field.extend(doc.field);
+    merged.schemas.extend(doc.schemas);
+    merged.enums.extend(doc.enums);
+    merged.proto.extend(doc.proto);
+    Ok(())
+}
+
+fn parse_json(config: &str) -> RpResult<Json> {
+    match Json::from_str(config) {
+        Ok(json) => Ok(json),
+        Err(ParserError::SyntaxError(code, line, col)) => {
+            fail!((ErrorKind::ConfigError,
+                   "json syntax error",
+                   format!("{} at line {} column {}", json::error_str(code), line, col)))
+        }
+        Err(ParserError::IoError(err)) => fail!(err),
+    }
+}
+
+/// the pattern with alias spelling is stored as the type name, unknown pattern is kept
+fn canonical_pattern(pattern: &str) -> String {
+    get_canonical_pattern(pattern).unwrap_or(pattern.to_string())
+}
+
+fn parse_types(json: &Json) -> RpResult<Vec<String>> {
+    let array = unwrap_or!(json.as_array(), {
+        fail!((ErrorKind::ConfigError, "bad section", "types must be array".to_string()))
+    });
+    let mut types = vec![];
+    for t in array {
+        let name = unwrap_or!(t.as_string().and_then(get_canonical_name), {
+            fail!((ErrorKind::ConfigError, "bad section", format!("types has unknown type {}", t)))
+        });
+        types.push(name.to_string());
+    }
+    Ok(types)
+}
+
+fn parse_u16(json: Option<&Json>) -> Option<u16> {
+    json.and_then(|j| j.as_u64()).and_then(|i| if i <= u16::max_value() as u64 {
+        Some(i as u16)
+    } else {
+        None
+    })
+}
+
+fn parse_field(name: &str, json: &Json) -> RpResult<Field> {
+    ensure!(json.is_object(),
+            (ErrorKind::ConfigError, "bad field", format!("field {} must be object", name)));
+    let index = unwrap_or!(parse_u16(json.find("index")), {
+        fail!((ErrorKind::ConfigError, "bad field", format!("field {} index must be u16", name)))
+    });
+    let pattern = unwrap_or!(json.find("pattern").and_then(|p| p.as_string()), {
+        fail!((ErrorKind::ConfigError,
+               "bad field",
+               format!("field {} pattern must be string", name)))
+    });
+    let scale = match json.find("scale") {
+        Some(scale) => {
+            match scale.as_u64() {
+                Some(s) if s > 0 && s <= u32::max_value() as u64 => Some(s as u32),
+                _ => {
+                    fail!((ErrorKind::ConfigError,
+                           "bad field",
+                           format!("field {} scale must be u32 above zero", name)))
+                }
+            }
+        }
+        None => None,
+    };
+    let required = match json.find("required") {
+        Some(required) => {
+            unwrap_or!(required.as_boolean(), {
+                fail!((ErrorKind::ConfigError,
+                       "bad field",
+                       format!("field {} required must be bool", name)))
+            })
+        }
+        None => false,
+    };
+    let pattern = canonical_pattern(pattern);
+    // the default of enum is the name
+    let t = match get_enum_name(&pattern) {
+        Some(_) if pattern.ends_with("[]") => TYPE_ASTR,
+        Some(_) => TYPE_STR,
+        None => get_type_by_name(&pattern),
+    };
+    let default = match json.find("default") {
+        Some(default) => {
+            Some(unwrap_or!(json_to_value(t, default), {
+                fail!((ErrorKind::ConfigError,
+                       "bad field",
+                       format!("field {} default {} is not {}", name, default, pattern)))
+            }))
+        }
+        None => None,
+    };
+    let (deprecated, desc) = try!(parse_meta(json, &format!("field {}", name), "bad field"));
+    Ok(Field {
+        index: index,
+        pattern: pattern,
+        scale: scale,
+        required: required,
+        default: default,
+        deprecated: deprecated,
+        desc: desc,
+    })
+}
+
+/// the "deprecated" and "desc" of the field or proto
+fn parse_meta(json: &Json, what: &str, bad: &'static str) -> RpResult<(bool, String)> {
+    let deprecated = match json.find("deprecated") {
+        Some(deprecated) => {
+            unwrap_or!(deprecated.as_boolean(), {
+                fail!((ErrorKind::ConfigError, bad, format!("{} deprecated must be bool", what)))
+            })
+        }
+        None => false,
+    };
+    let desc = match json.find("desc") {
+        Some(desc) => {
+            unwrap_or!(desc.as_string(), {
+                fail!((ErrorKind::ConfigError, bad, format!("{} desc must be string", what)))
+            })
+        }
+        None => "",
+    };
+    Ok((deprecated, desc.to_string()))
+}
+
+fn insert_meta_json(object: &mut BTreeMap<String, Json>, deprecated: bool, desc: &str) {
+    if deprecated {
+        object.insert("deprecated".to_string(), true.to_json());
+    }
+    if !desc.is_empty() {
+        object.insert("desc".to_string(), desc.to_json());
+    }
+}
+
+fn json_to_number(json: &Json, min: i64, max: i64) -> Option<i64> {
+    json.as_i64().and_then(|i| if i >= min && i <= max { Some(i) } else { None })
+}
+
+/// the value of type t in the json, None if the json is not the type, map is not supported
+fn json_to_value(t: u16, json: &Json) -> Option<Value> {
+    if t > TYPE_STEP {
+        let mut array = vec![];
+        for sub_json in unwrap_or!(json.as_array(), return None) {
+            array.push(unwrap_or!(json_to_value(t - TYPE_STEP, sub_json), return None));
+        }
+        return match new_array_value(t, array) {
+            Value::Nil => None,
+            value => Some(value),
+        };
+    }
+    match t {
+        TYPE_U8 => json_to_number(json, 0, u8::max_value() as i64).map(|i| Value::U8(i as u8)),
+        TYPE_I8 => {
+            json_to_number(json, i8::min_value() as i64, i8::max_value() as i64)
+                .map(|i| Value::I8(i as i8))
+        }
+        TYPE_U16 => json_to_number(json, 0, u16::max_value() as i64).map(|i| Value::U16(i as u16)),
+        TYPE_I16 => {
+            json_to_number(json, i16::min_value() as i64, i16::max_value() as i64)
+                .map(|i| Value::I16(i as i16))
+        }
+        TYPE_U32 => json_to_number(json, 0, u32::max_value() as i64).map(|i| Value::U32(i as u32)),
+        TYPE_I32 => {
+            json_to_number(json, i32::min_value() as i64, i32::max_value() as i64)
+                .map(|i| Value::I32(i as i32))
+        }
+        TYPE_U64 => json.as_u64().map(Value::U64),
+        TYPE_I64 => json.as_i64().map(Value::I64),
+        TYPE_FLOAT => json.as_f64().map(|f| Value::Float(f as f32)),
+        TYPE_F32 => json.as_f64().map(|f| Value::F32(f as f32)),
+        TYPE_F64 => json.as_f64().map(Value::F64),
+        TYPE_BOOL => json.as_boolean().map(Value::Bool),
+        TYPE_STR => json.as_string().map(|s| Value::Str(s.to_string())),
+        TYPE_RAW => json.as_string().map(|s| Value::Raw(s.as_bytes().to_vec())),
+        _ => None,
+    }
+}
+
+fn value_to_json(value: &Value) -> Json {
+    if let Some(val) = get_array_values(value) {
+        return Json::Array(val.iter().map(value_to_json).collect());
+    }
+    match *value {
+        Value::U8(val) => val.to_json(),
+        Value::I8(val) => val.to_json(),
+        Value::U16(val) => val.to_json(),
+        Value::I16(val) => val.to_json(),
+        Value::U32(val) => val.to_json(),
+        Value::I32(val) => val.to_json(),
+        Value::U64(val) => val.to_json(),
+        Value::I64(val) => val.to_json(),
+        Value::Float(val) | Value::F32(val) => (val as f64).to_json(),
+        Value::F64(val) => val.to_json(),
+        Value::Bool(val) => val.to_json(),
+        Value::Str(ref val) => val.to_json(),
+        Value::Raw(ref val) => String::from_utf8_lossy(val).to_json(),
+        Value::Map(ref val) => {
+            Json::Object(val.iter().map(|(k, v)| (k.clone(), value_to_json(v))).collect())
+        }
+        _ => Json::Null,
+    }
+}
+
+fn parse_enum(name: &str, json: &Json) -> RpResult<HashMap<String, u16>> {
+    let object = unwrap_or!(json.as_object(), {
+        fail!((ErrorKind::ConfigError, "bad enum", format!("enum {} must be object", name)))
+    });
+    let mut values = HashMap::new();
+    for (value_name, value) in object {
+        let v = unwrap_or!(parse_u16(Some(value)), {
+            fail!((ErrorKind::ConfigError,
+                   "bad enum",
+                   format!("enum {} value {} must be u16", name, value_name)))
+        });
+        values.insert(value_name.clone(), v);
+    }
+    Ok(values)
+}
+
+fn parse_fields(json: &Json) -> RpResult<HashMap<String, Field>> {
+    let object = unwrap_or!(json.as_object(), {
+        fail!((ErrorKind::ConfigError, "bad section", "field must be object".to_string()))
+    });
+    let mut fields = HashMap::new();
+    for (name, value) in object {
+        fields.insert(name.clone(), try!(parse_field(name, value)));
+    }
+    Ok(fields)
+}
+
+fn parse_proto(name: &str, json: &Json) -> RpResult<Proto> {
+    ensure!(json.is_object(),
+            (ErrorKind::ConfigError, "bad proto", format!("proto {} must be object", name)));
+    let msg_type = match json.find("msg_type") {
+        Some(msg_type) => {
+            unwrap_or!(msg_type.as_string(), {
+                fail!((ErrorKind::ConfigError,
+                       "bad proto",
+                       format!("proto {} msg_type must be string", name)))
+            })
+        }
+        None => "",
+    };
+    let index = match json.find("index") {
+        Some(index) => {
+            Some(unwrap_or!(parse_u16(Some(index)), {
+                fail!((ErrorKind::ConfigError,
+                       "bad proto",
+                       format!("proto {} index must be u16", name)))
+            }))
+        }
+        None => None,
+    };
+    let array = unwrap_or!(json.find("args").and_then(|a| a.as_array()), {
+        fail!((ErrorKind::ConfigError, "bad proto", format!("proto {} args must be array", name)))
+    });
+    let mut args = vec![];
+    let mut arg_names = vec![];
+    for (i, arg) in array.iter().enumerate() {
+        // the arg is the type name, or { "name" : arg name, "type" : type name }
+        let (arg_name, pattern) = match *arg {
+            Json::String(ref pattern) => ("", pattern.as_str()),
+            _ => {
+                let arg_name = arg.find("name").and_then(|n| n.as_string());
+                let pattern = arg.find("type").and_then(|t| t.as_string());
+                match (arg_name, pattern) {
+                    (Some(arg_name), Some(pattern)) if !arg_name.is_empty() => (arg_name, pattern),
+                    _ => {
+                        fail!((ErrorKind::ConfigError,
+                               "bad proto",
+                               format!("proto {} arg {} must be string or object with name and \
+                                        type",
+                                       name,
+                                       i)))
+                    }
+                }
+            }
+        };
+        args.push(canonical_pattern(pattern));
+        arg_names.push(arg_name.to_string());
+    }
+    let direction = match json.find("direction") {
+        Some(direction) => {
+            Some(unwrap_or!(direction.as_string().and_then(Direction::from_name), {
+                fail!((ErrorKind::ConfigError,
+                       "bad proto",
+                       format!("proto {} direction must be c2s or s2c", name)))
+            }))
+        }
+        None => None,
+    };
+    let (deprecated, desc) = try!(parse_meta(json, &format!("proto {}", name), "bad proto"));
+    Ok(Proto {
+        msg_type: msg_type.to_string(),
+        args: args,
+        arg_names: arg_names,
+        index: index,
+        direction: direction,
+        deprecated: deprecated,
+        desc: desc,
+    })
+}
+
+fn parse_protos(json: &Json) -> RpResult<HashMap<String, Proto>> {
+    let object = unwrap_or!(json.as_object(), {
+        fail!((ErrorKind::ConfigError, "bad section", "proto must be object".to_string()))
+    });
+    let mut protos = HashMap::new();
+    for (name, value) in object {
+        protos.insert(name.clone(), try!(parse_proto(name, value)));
+    }
+    Ok(protos)
 }
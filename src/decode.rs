@@ -1,19 +1,25 @@
 use std::collections::HashMap;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::mem;
 
 use Config;
+use Report;
 use Value;
 use Buffer;
 use Field;
 // use Proto;
 use RpResult;
 use ErrorKind;
+use check_required_fields;
 use {TYPE_NIL, TYPE_U8, TYPE_I8, TYPE_U16, TYPE_I16, TYPE_U32, TYPE_I32, TYPE_FLOAT, TYPE_STR,
-     TYPE_RAW, TYPE_MAP};
+     TYPE_RAW, TYPE_MAP, TYPE_U64, TYPE_I64,
+     TYPE_F32, TYPE_F64, TYPE_BOOL, TYPE_STEP};
 use {TYPE_AU8, TYPE_AI8, TYPE_AU16, TYPE_AI16, TYPE_AU32, TYPE_AI32, TYPE_AFLOAT, TYPE_ASTR,
-     TYPE_ARAW, TYPE_AMAP};
-use {get_name_by_type, get_type_by_name};
+     TYPE_ARAW, TYPE_AMAP, TYPE_AU64, TYPE_AI64,
+     TYPE_AF32, TYPE_AF64, TYPE_ABOOL};
+use {get_pattern_by_type, get_type_by_name, get_type_by_value, is_nested_array_type, is_legacy_type,
+     get_schema_name, get_enum_name, coerce_number};
+use {TYPE_LONG_STR, TYPE_LONG_RAW, DEFAULT_FLOAT_SCALE, NULL_ARG_INDEX};
 
 pub fn decode_number(buffer: &mut Buffer, pattern: u16) -> RpResult<Value> {
     match pattern {
@@ -51,11 +57,43 @@ pub fn decode_number(buffer: &mut Buffer, pattern: u16) -> RpResult<Value> {
             let val = unsafe { mem::transmute::<[u8; 4], i32>(*data) };
             Ok(Value::from(i32::from_le(val)))
         }
-        TYPE_FLOAT => {
+        TYPE_U64 => {
+            let data: &mut [u8; 8] = &mut [0, 0, 0, 0, 0, 0, 0, 0];
+            try_read!(buffer.read(data), data.len());
+            let val = unsafe { mem::transmute::<[u8; 8], u64>(*data) };
+            Ok(Value::from(u64::from_le(val)))
+        }
+        TYPE_I64 => {
+            let data: &mut [u8; 8] = &mut [0, 0, 0, 0, 0, 0, 0, 0];
+            try_read!(buffer.read(data), data.len());
+            let val = unsafe { mem::transmute::<[u8; 8], i64>(*data) };
+            Ok(Value::from(i64::from_le(val)))
+        }
+        TYPE_F32 => {
             let data: &mut [u8; 4] = &mut [0, 0, 0, 0];
             try_read!(buffer.read(data), data.len());
-            let val = unsafe { mem::transmute::<[u8; 4], i32>(*data) };
-            Ok(Value::from(val as f32 / 1000.0))
+            let val = unsafe { mem::transmute::<[u8; 4], u32>(*data) };
+            Ok(Value::F32(f32::from_bits(u32::from_le(val))))
+        }
+        TYPE_F64 => {
+            let data: &mut [u8; 8] = &mut [0, 0, 0, 0, 0, 0, 0, 0];
+            try_read!(buffer.read(data), data.len());
+            let val = unsafe { mem::transmute::<[u8; 8], u64>(*data) };
+            Ok(Value::F64(f64::from_bits(u64::from_le(val))))
+        }
+        TYPE_FLOAT => decode_float(buffer, DEFAULT_FLOAT_SCALE),
+        TYPE_BOOL => {
+            let data: &mut [u8; 1] = &mut [0];
+            try_read!(buffer.read(data), data.len());
+            match data[0] {
+                0 => Ok(Value::Bool(false)),
+                1 => Ok(Value::Bool(true)),
+                v => {
+                    fail!((ErrorKind::ParseError,
+                           "bool must be 0 or 1",
+                           format!("read byte {}", v)))
+                }
+            }
         }
         _ => {
             unreachable!("not other numbers");
@@ -63,29 +101,51 @@ pub fn decode_number(buffer: &mut Buffer, pattern: u16) -> RpResult<Value> {
     }
 }
 
+/// read the fixed-point "float" which was written with the scale
+pub fn decode_float(buffer: &mut Buffer, scale: u32) -> RpResult<Value> {
+    let data: &mut [u8; 4] = &mut [0, 0, 0, 0];
+    try_read!(buffer.read(data), data.len());
+    let val = unsafe { mem::transmute::<[u8; 4], i32>(*data) };
+    Ok(Value::Float((i32::from_le(val) as f64 / scale as f64) as f32))
+}
+
+
+/// the u16 or u32 len of "str" or "raw", it can't be more than the bytes left in the buffer
+fn read_str_len(buffer: &mut Buffer, long: bool) -> RpResult<usize> {
+    let len = if !long {
+        let len: u16 = try!(decode_number(buffer, TYPE_U16)).into();
+        len as usize
+    } else {
+        let len: u32 = try!(decode_number(buffer, TYPE_U32)).into();
+        len as usize
+    };
+    ensure!(len <= buffer.len().saturating_sub(buffer.get_rpos()),
+            (ErrorKind::NoLeftSpaceError, "must left space to read "));
+    Ok(len)
+}
 
 pub fn decode_str_raw(buffer: &mut Buffer, pattern: u16) -> RpResult<Value> {
     match pattern {
-        TYPE_STR => {
-            let len: u16 = try!(decode_number(buffer, TYPE_U16)).into();
+        TYPE_STR | TYPE_LONG_STR => {
+            let len = try!(read_str_len(buffer, pattern == TYPE_LONG_STR));
             if len == 0 {
                 return Ok(Value::from(String::new()));
             }
-            let mut rv = vec![0; len as usize];
-            try_read!(buffer.read(&mut rv[..]), len as usize);
+            let mut rv = vec![0; len];
+            try_read!(buffer.read(&mut rv[..]), len);
             let val = String::from_utf8(rv);
             if val.is_err() {
                 fail!((ErrorKind::StringFormatError, "string format error"));
             }
             Ok(Value::from(val.ok().unwrap()))
         }
-        TYPE_RAW => {
-            let len: u16 = try!(decode_number(buffer, TYPE_U16)).into();
+        TYPE_RAW | TYPE_LONG_RAW => {
+            let len = try!(read_str_len(buffer, pattern == TYPE_LONG_RAW));
             if len == 0 {
                 return Ok(Value::from(vec![]))
             }
-            let mut rv = vec![0; len as usize];
-            try_read!(buffer.read(&mut rv[..]), len as usize);
+            let mut rv = vec![0; len];
+            try_read!(buffer.read(&mut rv[..]), len);
             Ok(Value::from(rv))
         }
         _ => {
@@ -95,85 +155,322 @@ pub fn decode_str_raw(buffer: &mut Buffer, pattern: u16) -> RpResult<Value> {
 }
 
 pub fn decode_map(buffer: &mut Buffer, config: &Config) -> RpResult<Value> {
+    decode_map_collect(buffer, config, &mut Report::new())
+}
+
+/// decode the map and collect the problems of this call into the report
+pub fn decode_map_collect(buffer: &mut Buffer,
+                          config: &Config,
+                          report: &mut Report)
+                          -> RpResult<Value> {
+    decode_map_by(buffer, config, None, report)
+}
+
+/// decode the map with the fields of the named schema instead of the config fields
+pub fn decode_map_schema(buffer: &mut Buffer, config: &Config, schema: &str) -> RpResult<Value> {
+    decode_map_by(buffer, config, Some(schema), &mut Report::new())
+}
+
+/// decode the map with the fields of the schema, or the config fields if none
+fn decode_map_by(buffer: &mut Buffer,
+                 config: &Config,
+                 schema: Option<&str>,
+                 report: &mut Report)
+                 -> RpResult<Value> {
+    let schema = match schema {
+        Some(name) => {
+            Some(unwrap_or!(config.get_schema(name), {
+                fail!((ErrorKind::MissingError,
+                       "missing the schema",
+                       format!("unknown schema {}", name)))
+            }))
+        }
+        None => None,
+    };
     let mut map = HashMap::<String, Value>::new();
     loop {
         let field = try!(read_field(buffer));
         if field.is_nil_type() {
+            // required and default are only for the schema maps
+            if let Some(schema) = schema {
+                let fields = schema.get_fields();
+                for (name, field) in fields {
+                    if let Some(ref default) = field.default {
+                        if !map.contains_key(name) {
+                            map.insert(name.clone(), default.clone());
+                        }
+                    }
+                }
+                try!(check_required_fields(fields, &map));
+            }
             return Ok(Value::from(map));
         }
-        let sub_value = try!(decode_field(buffer, config));
-        let name = config.get_field_index_name(&field.index);
-        if name.is_none() {
-            continue;
-        }
-        let name = name.map(|s| s.clone()).unwrap();
-        map.insert(name, sub_value);
+        let (define, name) = match schema {
+            Some(schema) => {
+                (schema.get_field_by_index(&field.index),
+                 schema.get_field_index_name(&field.index))
+            }
+            None => {
+                (config.get_field_by_index(&field.index),
+                 config.get_field_index_name(&field.index))
+            }
+        };
+        let sub_value = if is_legacy_type(get_type_by_name(&field.pattern)) {
+            try!(decode_field_by(buffer, config, define, report))
+        } else {
+            try!(decode_wrapped(buffer, config, &field, define, report))
+        };
+        let name = unwrap_or!(name, continue);
+        let define = define.unwrap();
+        try!(config.check_field_value(name, define, &sub_value, report));
+        let sub_value = match get_enum_name(&define.pattern) {
+            Some(enum_name) => try!(config.decode_enum(enum_name, sub_value, report)),
+            None => sub_value,
+        };
+        map.insert(name.clone(), sub_value);
     }
 }
 
+/// read the map value which encode_map wrapped as "raw" after the head of the real type
+fn decode_wrapped(buffer: &mut Buffer,
+                  config: &Config,
+                  field: &Field,
+                  define: Option<&Field>,
+                  report: &mut Report)
+                  -> RpResult<Value> {
+    let data = match try!(decode_field_by(buffer, config, None, report)) {
+        Value::Raw(data) => data,
+        value => {
+            fail!((ErrorKind::TypeNotMatchError,
+                   "must match type",
+                   format!("field index {} expect wrapped raw but {}",
+                           field.index,
+                           get_pattern_by_type(get_type_by_value(&value)))))
+        }
+    };
+    let mut inner = Buffer::new();
+    try!(inner.write(&data));
+    let value = try!(decode_field_by(&mut inner, config, define, report));
+    let t = get_type_by_value(&value);
+    ensure!(t == get_type_by_name(&field.pattern),
+            (ErrorKind::TypeNotMatchError,
+             "must match type",
+             format!("field index {} expect {} but {}",
+                     field.index,
+                     field.pattern,
+                     get_pattern_by_type(t))));
+    Ok(value)
+}
+
+/// read the head of the value, the unknown type fails with TypeNotMatchError
 pub fn read_field(buffer: &mut Buffer) -> RpResult<Field> {
     let index = try!(decode_number(buffer, TYPE_U16)).into();
-    let pattern = try!(decode_number(buffer, TYPE_U16)).into();
+    let t: u16 = try!(decode_number(buffer, TYPE_U16)).into();
+    let pattern = get_pattern_by_type(t);
+    ensure!(t == TYPE_NIL || get_type_by_name(&pattern) == t,
+            (ErrorKind::TypeNotMatchError, "must match type", format!("unknown type {}", t)));
     Ok(Field {
         index: index,
-        pattern: get_name_by_type(pattern).to_string(),
+        pattern: pattern,
+        scale: None,
+        required: false,
+        default: None,
+        deprecated: false,
+        desc: String::new(),
     })
 }
 
-fn decode_by_field(buffer: &mut Buffer, config: &Config, field: &Field) -> RpResult<Value> {
+fn decode_by_field(buffer: &mut Buffer,
+                   config: &Config,
+                   field: &Field,
+                   define: Option<&Field>,
+                   report: &mut Report)
+                   -> RpResult<Value> {
     let t = get_type_by_name(&*field.pattern);
     match t {
-        TYPE_U8 | TYPE_I8 | TYPE_U16 | TYPE_I16 | TYPE_U32 | TYPE_I32 | TYPE_FLOAT => {
-            decode_number(buffer, t)
-        }
-        TYPE_STR | TYPE_RAW => decode_str_raw(buffer, t),
-        TYPE_MAP => decode_map(buffer, config),
-        TYPE_AU8 => decode_array!(decode_field(buffer, config), Value::AU8, Value::U8),
-        TYPE_AI8 => decode_array!(decode_field(buffer, config), Value::AI8, Value::I8),
-        TYPE_AU16 => decode_array!(decode_field(buffer, config), Value::AU16, Value::U16),
-        TYPE_AI16 => decode_array!(decode_field(buffer, config), Value::AI16, Value::I16),
-        TYPE_AU32 => decode_array!(decode_field(buffer, config), Value::AU32, Value::U32),
-        TYPE_AI32 => decode_array!(decode_field(buffer, config), Value::AI32, Value::I32),
-        TYPE_AFLOAT => decode_array!(decode_field(buffer, config), Value::AFloat, Value::Float),
-        TYPE_ASTR => decode_array!(decode_field(buffer, config), Value::AStr, Value::Str),
-        TYPE_ARAW => decode_array!(decode_field(buffer, config), Value::ARaw, Value::Raw),
-        TYPE_AMAP => decode_array!(decode_field(buffer, config), Value::AMap, Value::Map),
+        TYPE_U8 | TYPE_I8 | TYPE_U16 | TYPE_I16 | TYPE_U32 | TYPE_I32 | TYPE_U64 | TYPE_I64 |
+        TYPE_F32 | TYPE_F64 | TYPE_BOOL => decode_number(buffer, t),
+        TYPE_FLOAT => decode_float(buffer, config.get_field_float_scale(define)),
+        TYPE_STR | TYPE_RAW | TYPE_LONG_STR | TYPE_LONG_RAW => decode_str_raw(buffer, t),
+        TYPE_MAP => {
+            let schema = define.and_then(|f| get_schema_name(&f.pattern));
+            decode_map_by(buffer, config, schema, report)
+        }
+        TYPE_AU8 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AU8, Value::U8)
+        }
+        TYPE_AI8 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AI8, Value::I8)
+        }
+        TYPE_AU16 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AU16, Value::U16)
+        }
+        TYPE_AI16 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AI16, Value::I16)
+        }
+        TYPE_AU32 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AU32, Value::U32)
+        }
+        TYPE_AI32 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AI32, Value::I32)
+        }
+        TYPE_AFLOAT => {
+            decode_array!(decode_field_by(buffer, config, define, report),
+                          Value::AFloat,
+                          Value::Float)
+        }
+        TYPE_ASTR => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AStr, Value::Str)
+        }
+        TYPE_ARAW => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::ARaw, Value::Raw)
+        }
+        TYPE_AMAP => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AMap, Value::Map)
+        }
+        TYPE_AU64 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AU64, Value::U64)
+        }
+        TYPE_AI64 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AI64, Value::I64)
+        }
+        TYPE_AF32 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AF32, Value::F32)
+        }
+        TYPE_AF64 => {
+            decode_array!(decode_field_by(buffer, config, define, report), Value::AF64, Value::F64)
+        }
+        TYPE_ABOOL => {
+            decode_array!(decode_field_by(buffer, config, define, report),
+                          Value::ABool,
+                          Value::Bool)
+        }
         TYPE_NIL => Ok(Value::Nil),
+        t if is_nested_array_type(t) => {
+            let mut value: Vec<Value> = vec![];
+            loop {
+                let sub_value = try!(decode_field_by(buffer, config, define, report));
+                if let Value::Nil = sub_value {
+                    break;
+                }
+                ensure!(get_type_by_value(&sub_value) == t - TYPE_STEP,
+                        (ErrorKind::TypeNotMatchError, "must match type"));
+                value.push(sub_value);
+            }
+            Ok(Value::AArray(t - TYPE_STEP, value))
+        }
         _ => fail!((ErrorKind::TypeNotMatchError, "must match type")),
     }
 }
 
 
 pub fn decode_field(buffer: &mut Buffer, config: &Config) -> RpResult<Value> {
+    decode_field_collect(buffer, config, &mut Report::new())
+}
+
+/// decode the value and collect the problems of this call into the report
+pub fn decode_field_collect(buffer: &mut Buffer,
+                            config: &Config,
+                            report: &mut Report)
+                            -> RpResult<Value> {
+    decode_field_by(buffer, config, None, report)
+}
+
+/// decode the value with the define of the field which contains it
+fn decode_field_by(buffer: &mut Buffer,
+                   config: &Config,
+                   define: Option<&Field>,
+                   report: &mut Report)
+                   -> RpResult<Value> {
     let field = try!(read_field(buffer));
     if field.is_nil_type() {
         return Ok(Value::Nil);
     }
-    decode_by_field(buffer, config, &field)
+    decode_by_field(buffer, config, &field, define, report)
 }
 
 pub fn decode_proto(buffer: &mut Buffer, config: &Config) -> RpResult<(String, Vec<Value>)> {
-    let name = try!(decode_str_raw(buffer, TYPE_STR)).into();
-    // TODO check proto choose to transfer
+    decode_proto_collect(buffer, config, &mut Report::new())
+}
+
+/// decode the proto and collect the problems of this call into the report
+pub fn decode_proto_collect(buffer: &mut Buffer,
+                            config: &Config,
+                            report: &mut Report)
+                            -> RpResult<(String, Vec<Value>)> {
+    let mut name: String = try!(decode_str_raw(buffer, TYPE_STR)).into();
+    if name.is_empty() {
+        let index: u16 = try!(decode_number(buffer, TYPE_U16)).into();
+        let index_name = unwrap_or!(config.get_proto_index_name(&index), {
+            fail!((ErrorKind::MissingError,
+                   "missing the index protocol",
+                   format!("unknown proto index {}", index)))
+        });
+        name = index_name.clone();
+    }
+    let proto = unwrap_or!(config.get_proto_by_name(&name), {
+        fail!((ErrorKind::TypeNotMatchError, "must match type", format!("unknown proto {}", name)))
+    });
+    try!(config.check_proto_direction(&name, proto, false));
     let mut value: Vec<Value> = vec![];
     loop {
-        let sub_value = try!(decode_field(buffer, config));
-        match sub_value {
-            Value::Nil => break,
-            _ => (),
+        let field = try!(read_field(buffer));
+        if field.is_nil_type() {
+            break;
+        }
+        if field.index == NULL_ARG_INDEX && get_type_by_name(&field.pattern) == TYPE_NIL {
+            value.push(Value::Nil);
+            continue;
         }
-        value.push(sub_value);
+        let define = proto.args.get(value.len()).map(|pattern| Field::new(pattern.clone()));
+        value.push(try!(decode_by_field(buffer, config, &field, define.as_ref(), report)));
     }
-    let proto = config.get_proto_by_name(&name);
-    match proto {
-        Some(val) => {
-            if val.args.len() != value.len() {
-                fail!((ErrorKind::TypeNotMatchError, "must match type"));
+    let min_args = proto.get_min_args();
+    if value.len() < min_args || value.len() > proto.args.len() {
+        let expect = if min_args == proto.args.len() {
+            min_args.to_string()
+        } else {
+            format!("{} to {}", min_args, proto.args.len())
+        };
+        fail!((ErrorKind::TypeNotMatchError,
+               "must match type",
+               format!("proto {} expect {} args but {}", name, expect, value.len())));
+    }
+    // the omitted trailing optional args are null
+    while value.len() < proto.args.len() {
+        value.push(Value::Nil);
+    }
+    for (i, sub_value) in value.iter_mut().enumerate() {
+        if let Err(err) = proto.check_arg(&name, i, sub_value) {
+            if !config.is_coerce_number() {
+                return Err(err);
             }
+            let t = get_type_by_name(&proto.args[i]);
+            *sub_value = unwrap_or!(coerce_number(sub_value, t), return Err(err));
         }
-        _ => {
-            fail!((ErrorKind::TypeNotMatchError, "must match type"));
+        if let Some(enum_name) = get_enum_name(&proto.args[i]) {
+            let value = mem::replace(sub_value, Value::Nil);
+            *sub_value = try!(config.decode_enum(enum_name, value, report));
         }
     }
     Ok((name, value))
 }
+
+/// decode the proto and the args by the arg names, the null arg is not in the map
+pub fn decode_proto_named(buffer: &mut Buffer,
+                          config: &Config)
+                          -> RpResult<(String, HashMap<String, Value>)> {
+    let (name, value) = try!(decode_proto(buffer, config));
+    let proto = config.get_proto_by_name(&name).unwrap();
+    let mut map = HashMap::<String, Value>::new();
+    for (i, (arg_name, sub_value)) in proto.arg_names.iter().zip(value).enumerate() {
+        ensure!(!arg_name.is_empty(),
+                (ErrorKind::MissingError,
+                 "missing the arg name",
+                 format!("proto {} arg {} has no name", name, i)));
+        if sub_value != Value::Nil {
+            map.insert(arg_name.clone(), sub_value);
+        }
+    }
+    Ok((name, map))
+}
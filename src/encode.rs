@@ -1,19 +1,28 @@
+use std::collections::HashMap;
 use std::io::Write;
 use std::mem;
 
 use Config;
+use Report;
 use Value;
 use Buffer;
 use Field;
 use RpResult;
 use ErrorKind;
-use {get_type_by_name, get_name_by_type, get_array_contains_type, get_type_by_value};
+use check_required_fields;
+use {get_type_by_name, get_array_contains_type, get_type_by_value, is_legacy_type, is_array_type,
+     get_pattern_by_type,
+     get_schema_name, get_enum_name, get_optional_base};
 
-use STR_TYPE_NIL;
+use {TYPE_NIL, TYPE_LONG_STR, TYPE_LONG_RAW, STR_TYPE_NIL, DEFAULT_FLOAT_SCALE, NULL_ARG_INDEX};
 
 fn write_str_field(buffer: &mut Buffer, pattern: &str) -> RpResult<bool> {
+    write_type_field(buffer, get_type_by_name(pattern))
+}
+
+fn write_type_field(buffer: &mut Buffer, t: u16) -> RpResult<bool> {
     try!(encode_number(buffer, &Value::from(0 as u16)));
-    try!(encode_number(buffer, &Value::U16(get_type_by_name(pattern))));
+    try!(encode_number(buffer, &Value::U16(t)));
     Ok(true)
 }
 
@@ -46,23 +55,80 @@ pub fn encode_number(buffer: &mut Buffer, value: &Value) -> RpResult<()> {
         Value::I32(val) => {
             try!(buffer.write(unsafe { &mem::transmute::<i32, [u8; 4]>(val.to_le()) }));
         }
+        Value::U64(val) => {
+            try!(buffer.write(unsafe { &mem::transmute::<u64, [u8; 8]>(val.to_le()) }));
+        }
+        Value::I64(val) => {
+            try!(buffer.write(unsafe { &mem::transmute::<i64, [u8; 8]>(val.to_le()) }));
+        }
+        Value::F32(val) => {
+            try!(buffer.write(unsafe { &mem::transmute::<u32, [u8; 4]>(val.to_bits().to_le()) }));
+        }
+        Value::F64(val) => {
+            try!(buffer.write(unsafe { &mem::transmute::<u64, [u8; 8]>(val.to_bits().to_le()) }));
+        }
         Value::Float(val) => {
-            let val = (val * 1000.0) as i32;
-            try!(buffer.write(unsafe { &mem::transmute::<i32, [u8; 4]>(val.to_le()) }));
+            try!(encode_float(buffer, val, DEFAULT_FLOAT_SCALE));
+        }
+        Value::Bool(val) => {
+            try!(buffer.write(&[val as u8]));
         }
         _ => unreachable!("encode_number only"),
     }
     Ok(())
 }
 
+/// write the fixed-point "float" truncated as before, the scaled value must fit in i32
+pub fn encode_float(buffer: &mut Buffer, val: f32, scale: u32) -> RpResult<()> {
+    let scaled = (val * scale as f32) as f64;
+    if !(scaled > i32::min_value() as f64 - 1.0 && scaled < i32::max_value() as f64 + 1.0) {
+        fail!((ErrorKind::OverflowError,
+               "float value overflow",
+               format!("{} with scale {} not fit in i32", val, scale)));
+    }
+    let val = scaled as i32;
+    try!(buffer.write(unsafe { &mem::transmute::<i32, [u8; 4]>(val.to_le()) }));
+    Ok(())
+}
+
+/// the len is u16, or u32 if the head type is TYPE_LONG_STR or TYPE_LONG_RAW
+fn write_str_len(buffer: &mut Buffer, len: usize, long: bool) -> RpResult<()> {
+    if !long {
+        ensure!(len <= u16::max_value() as usize,
+                (ErrorKind::OverflowError,
+                 "str or raw too long",
+                 format!("len {} not fit in u16, encode it by encode_field", len)));
+        return encode_number(buffer, &Value::U16(len as u16));
+    }
+    ensure!(len <= u32::max_value() as usize,
+            (ErrorKind::OverflowError,
+             "str or raw too long",
+             format!("len {} not fit in u32", len)));
+    encode_number(buffer, &Value::U32(len as u32))
+}
+
+/// the type of the value head, "str" and "raw" longer than u16 have the long types
+fn get_head_type(value: &Value) -> u16 {
+    match *value {
+        Value::Str(ref val) if val.len() > u16::max_value() as usize => TYPE_LONG_STR,
+        Value::Raw(ref val) if val.len() > u16::max_value() as usize => TYPE_LONG_RAW,
+        _ => get_type_by_value(value),
+    }
+}
+
+/// write the "str" or "raw" with u16 len, it fails with OverflowError if it is longer
 pub fn encode_str_raw(buffer: &mut Buffer, value: &Value) -> RpResult<()> {
+    write_str_raw(buffer, value, false)
+}
+
+fn write_str_raw(buffer: &mut Buffer, value: &Value, long: bool) -> RpResult<()> {
     match *value {
         Value::Str(ref val) => {
-            try!(encode_number(buffer, &Value::U16(val.len() as u16)));
+            try!(write_str_len(buffer, val.len(), long));
             try!(append_and_align(buffer, &val.as_bytes()[..]));
         }
         Value::Raw(ref val) => {
-            try!(encode_number(buffer, &Value::U16(val.len() as u16)));
+            try!(write_str_len(buffer, val.len(), long));
             try!(append_and_align(buffer, &val[..]));
         }
         _ => unreachable!("encode_str_raw only"),
@@ -71,11 +137,75 @@ pub fn encode_str_raw(buffer: &mut Buffer, value: &Value) -> RpResult<()> {
 }
 
 pub fn encode_map(buffer: &mut Buffer, config: &Config, value: &Value) -> RpResult<()> {
+    encode_map_collect(buffer, config, value, &mut Report::new())
+}
+
+/// encode the map and collect the problems of this call into the report
+pub fn encode_map_collect(buffer: &mut Buffer,
+                          config: &Config,
+                          value: &Value,
+                          report: &mut Report)
+                          -> RpResult<()> {
+    encode_map_by(buffer, config, None, value, report)
+}
+
+/// encode the map with the fields of the named schema instead of the config fields
+pub fn encode_map_schema(buffer: &mut Buffer,
+                         config: &Config,
+                         schema: &str,
+                         value: &Value)
+                         -> RpResult<()> {
+    encode_map_by(buffer, config, Some(schema), value, &mut Report::new())
+}
+
+/// encode the map with the fields of the schema, or the config fields if none
+fn encode_map_by(buffer: &mut Buffer,
+                 config: &Config,
+                 schema: Option<&str>,
+                 value: &Value,
+                 report: &mut Report)
+                 -> RpResult<()> {
+    let schema = match schema {
+        Some(name) => {
+            Some(unwrap_or!(config.get_schema(name), {
+                fail!((ErrorKind::MissingError,
+                       "missing the schema",
+                       format!("unknown schema {}", name)))
+            }))
+        }
+        None => None,
+    };
     match *value {
         Value::Map(ref val) => {
+            // required and default are only for the schema maps
+            if let Some(schema) = schema {
+                try!(check_required_fields(schema.get_fields(), val));
+            }
             for (name, sub_value) in val {
-                if try!(write_field(buffer, config.get_field_by_name(name))) {
-                    try!(encode_field(buffer, config, sub_value));
+                let field = match schema {
+                    Some(schema) => schema.get_field_by_name(name),
+                    None => config.get_field_by_name(name),
+                };
+                let number;
+                let sub_value = match field.and_then(|f| get_enum_name(&f.pattern)) {
+                    Some(enum_name) => {
+                        number = try!(config.encode_enum(enum_name, sub_value, report));
+                        &number
+                    }
+                    None => sub_value,
+                };
+                match field {
+                    Some(field) => try!(config.check_field_value(name, field, sub_value, report)),
+                    None => try!(config.drop_unknown_key(name, report)),
+                }
+                if let Some(field) = field {
+                    if !is_legacy_type(get_type_by_value(sub_value)) {
+                        try!(encode_wrapped(buffer, config, field, sub_value, report));
+                        continue;
+                    }
+                }
+                if try!(write_field(buffer, field)) {
+                    try!(encode_field_by(buffer, config, field, sub_value, report));
                 }
             }
             try!(write_str_field(buffer, STR_TYPE_NIL));
@@ -85,6 +215,20 @@ pub fn encode_map(buffer: &mut Buffer, config: &Config, value: &Value) -> RpResu
     Ok(())
 }
 
+/// write the real type in the field head and the value as "raw", the old decoders which
+/// don't know the type read it as "raw" and can skip it
+fn encode_wrapped(buffer: &mut Buffer,
+                  config: &Config,
+                  field: &Field,
+                  value: &Value,
+                  report: &mut Report)
+                  -> RpResult<()> {
+    let mut inner = Buffer::new();
+    try!(encode_field_by(&mut inner, config, Some(field), value, report));
+    try!(encode_number(buffer, &Value::U16(field.index)));
+    try!(encode_number(buffer, &Value::U16(get_type_by_value(value))));
+    encode_field_by(buffer, config, None, &Value::Raw(inner.get_data().clone()), report)
+}
 
 pub fn write_field(buffer: &mut Buffer, field: Option<&Field>) -> RpResult<bool> {
     if field.is_none() {
@@ -97,7 +241,33 @@ pub fn write_field(buffer: &mut Buffer, field: Option<&Field>) -> RpResult<bool>
 }
 
 pub fn encode_field(buffer: &mut Buffer, config: &Config, value: &Value) -> RpResult<()> {
-    try!(write_str_field(buffer, get_name_by_type(get_type_by_value(value))));
+    encode_field_collect(buffer, config, value, &mut Report::new())
+}
+
+/// encode the value and collect the problems of this call into the report
+pub fn encode_field_collect(buffer: &mut Buffer,
+                            config: &Config,
+                            value: &Value,
+                            report: &mut Report)
+                            -> RpResult<()> {
+    encode_field_by(buffer, config, None, value, report)
+}
+
+/// encode the value with the define of the field which contains it
+fn encode_field_by(buffer: &mut Buffer,
+                   config: &Config,
+                   field: Option<&Field>,
+                   value: &Value,
+                   report: &mut Report)
+                   -> RpResult<()> {
+    if let Value::AArray(t, _) = *value {
+        ensure!(is_array_type(t),
+                (ErrorKind::TypeNotMatchError,
+                 "must match type",
+                 format!("array of {} is not the array of arrays", get_pattern_by_type(t))));
+    }
+    let t = get_head_type(value);
+    try!(write_type_field(buffer, t));
     match *value {
         Value::U8(_) |
         Value::I8(_) |
@@ -105,14 +275,22 @@ pub fn encode_field(buffer: &mut Buffer, config: &Config, value: &Value) -> RpRe
         Value::I16(_) |
         Value::U32(_) |
         Value::I32(_) |
-        Value::Float(_) => {
+        Value::U64(_) |
+        Value::I64(_) |
+        Value::F32(_) |
+        Value::F64(_) |
+        Value::Bool(_) => {
             try!(encode_number(buffer, value));
         }
+        Value::Float(val) => {
+            try!(encode_float(buffer, val, config.get_field_float_scale(field)));
+        }
         Value::Str(_) | Value::Raw(_) => {
-            try!(encode_str_raw(buffer, value));
+            try!(write_str_raw(buffer, value, t == TYPE_LONG_STR || t == TYPE_LONG_RAW));
         }
         Value::Map(_) => {
-            try!(encode_map(buffer, config, value));
+            let schema = field.and_then(|f| get_schema_name(&f.pattern));
+            try!(encode_map_by(buffer, config, schema, value, report));
         }
         Value::Nil => {}
         Value::AU8(ref val) |
@@ -124,11 +302,17 @@ pub fn encode_field(buffer: &mut Buffer, config: &Config, value: &Value) -> RpRe
         Value::AFloat(ref val) |
         Value::AStr(ref val) |
         Value::ARaw(ref val) |
-        Value::AMap(ref val) => {
+        Value::AMap(ref val) |
+        Value::AU64(ref val) |
+        Value::AI64(ref val) |
+        Value::AF32(ref val) |
+        Value::AF64(ref val) |
+        Value::ABool(ref val) |
+        Value::AArray(_, ref val) => {
             let must_type = get_array_contains_type(value);
             for v in val {
                 check_vailed!(v, must_type);
-                try!(encode_field(buffer, config, v));
+                try!(encode_field_by(buffer, config, field, v, report));
             }
             try!(write_str_field(buffer, STR_TYPE_NIL));
         }
@@ -141,17 +325,86 @@ pub fn encode_proto(buffer: &mut Buffer,
                     name: &String,
                     infos: Vec<Value>)
                     -> RpResult<()> {
+    encode_proto_collect(buffer, config, name, infos, &mut Report::new())
+}
+
+/// encode the proto and collect the problems of this call into the report
+pub fn encode_proto_collect(buffer: &mut Buffer,
+                            config: &Config,
+                            name: &String,
+                            mut infos: Vec<Value>,
+                            report: &mut Report)
+                            -> RpResult<()> {
     let proto = config.get_proto_by_name(name);
     ensure!(proto.is_some(),
             (ErrorKind::MissingError, "missing the name protocol"));
     let proto = proto.unwrap();
-    ensure!(proto.args.len() == infos.len(),
+    try!(config.check_proto_direction(name, proto, true));
+    ensure!(proto.get_min_args() <= infos.len() && infos.len() <= proto.args.len(),
             (ErrorKind::TypeNotMatchError, "the data num not match protocol args num"));
-    try!(encode_str_raw(buffer, &Value::Str(name.clone())));
-    for info in &infos {
-        // TODO match args
-        try!(encode_field(buffer, config, info));
+    for (info, pattern) in infos.iter_mut().zip(&proto.args) {
+        if let Some(enum_name) = get_enum_name(pattern) {
+            *info = try!(config.encode_enum(enum_name, info, report));
+        }
+    }
+    for (i, info) in infos.iter().enumerate() {
+        try!(proto.check_arg(name, i, info));
+    }
+    match proto.index {
+        Some(index) if config.is_proto_by_index() => {
+            // the empty name mark the index follow
+            try!(encode_str_raw(buffer, &Value::Str(String::new())));
+            try!(encode_number(buffer, &Value::U16(index)));
+        }
+        _ => try!(encode_str_raw(buffer, &Value::Str(name.clone()))),
+    }
+    for (info, pattern) in infos.iter().zip(&proto.args) {
+        if *info == Value::Nil {
+            // check_arg make sure the arg is optional
+            try!(encode_number(buffer, &Value::U16(NULL_ARG_INDEX)));
+            try!(encode_number(buffer, &Value::U16(TYPE_NIL)));
+            continue;
+        }
+        let define = Field::new(pattern.clone());
+        try!(encode_field_by(buffer, config, Some(&define), info, report));
     }
     try!(write_str_field(buffer, STR_TYPE_NIL));
     Ok(())
 }
+
+/// encode the args by the arg names, the absent optional arg is nil
+pub fn encode_proto_named(buffer: &mut Buffer,
+                          config: &Config,
+                          name: &String,
+                          mut infos: HashMap<String, Value>)
+                          -> RpResult<()> {
+    let proto = unwrap_or!(config.get_proto_by_name(name), {
+        fail!((ErrorKind::MissingError, "missing the name protocol"))
+    });
+    let mut args = vec![];
+    for (i, arg_name) in proto.arg_names.iter().enumerate() {
+        ensure!(!arg_name.is_empty(),
+                (ErrorKind::MissingError,
+                 "missing the arg name",
+                 format!("proto {} arg {} has no name", name, i)));
+        match infos.remove(arg_name) {
+            Some(value) => args.push(value),
+            None if get_optional_base(&proto.args[i]).is_some() => args.push(Value::Nil),
+            None => {
+                fail!((ErrorKind::MissingError,
+                       "missing the arg",
+                       format!("proto {} arg {} is missing", name, arg_name)))
+            }
+        }
+    }
+    if let Some(arg_name) = infos.keys().min() {
+        fail!((ErrorKind::TypeNotMatchError,
+               "the data not match protocol args",
+               format!("proto {} has no arg {}", name, arg_name)));
+    }
+    // the trailing null args can be omitted
+    while args.len() > proto.get_min_args() && args.last() == Some(&Value::Nil) {
+        args.pop();
+    }
+    encode_proto(buffer, config, name, args)
+}
@@ -4,13 +4,23 @@ extern crate rustc_serialize;
 pub mod macros;
 pub mod values;
 pub mod config;
+pub mod builder;
+pub mod compat;
+pub mod shared;
 pub mod buffer;
 pub mod encode;
 pub mod decode;
 
 pub use values::*;
-pub use config::{Config, Field, Proto};
+pub use config::{Config, Field, Proto, Schema, Enum, Report, Direction, MismatchPolicy,
+                 UnknownKeyPolicy, check_required_fields};
+pub use builder::ConfigBuilder;
+pub use compat::{Change, check_compatible};
+pub use shared::SharedConfig;
 pub use buffer::Buffer;
-pub use encode::{encode_proto, encode_field, write_field, encode_number, encode_map,
-                 encode_str_raw};
-pub use decode::{decode_proto, decode_field, read_field, decode_number, decode_map, decode_str_raw};
+pub use encode::{encode_proto, encode_field, write_field, encode_number, encode_float, encode_map,
+                 encode_str_raw, encode_proto_named, encode_field_collect, encode_map_collect,
+                 encode_proto_collect, encode_map_schema};
+pub use decode::{decode_proto, decode_field, read_field, decode_number, decode_float, decode_map,
+                 decode_str_raw, decode_proto_named, decode_field_collect, decode_map_collect,
+                 decode_proto_collect, decode_map_schema};
@@ -0,0 +1,68 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+use Config;
+use RpResult;
+use ErrorKind;
+use {Change, check_compatible};
+
+/// the Config shared by threads which can be reloaded at runtime,
+/// the encode or decode in flight keep using the Config they got
+pub struct SharedConfig {
+    current: RwLock<Arc<Config>>,
+    callbacks: Mutex<Vec<Arc<Fn(&[Change]) + Send + Sync>>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> SharedConfig {
+        SharedConfig {
+            current: RwLock::new(Arc::new(config)),
+            callbacks: Mutex::new(vec![]),
+        }
+    }
+
+    /// the current Config, reload won't change the returned one
+    pub fn get(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// call the callback with the changes after every reload
+    pub fn on_change<F>(&self, callback: F)
+        where F: Fn(&[Change]) + Send + Sync + 'static
+    {
+        self.callbacks.lock().unwrap().push(Arc::new(callback));
+    }
+
+    /// replace the current Config if the new one is valid, return the changes
+    pub fn replace(&self, config: Config) -> RpResult<Vec<Change>> {
+        let problems = config.validate();
+        ensure!(problems.is_empty(),
+                (ErrorKind::ConfigError, "invalid schema", problems.join("; ")));
+        let changes = {
+            let mut current = self.current.write().unwrap();
+            let changes = check_compatible(&current, &config);
+            *current = Arc::new(config);
+            changes
+        };
+        // release the lock first, the callback may call on_change
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        for callback in &callbacks {
+            callback(&changes);
+        }
+        Ok(changes)
+    }
+
+    /// reload from the protocol file, the settings of the current Config are kept
+    pub fn reload_from_file<P: AsRef<Path>>(&self, path: P) -> RpResult<Vec<Change>> {
+        let mut config = try!(Config::from_file(path));
+        config.copy_settings(&self.get());
+        self.replace(config)
+    }
+
+    /// reload from the protocol dir, the settings of the current Config are kept
+    pub fn reload_from_dir<P: AsRef<Path>>(&self, dir: P) -> RpResult<Vec<Change>> {
+        let mut config = try!(Config::from_dir(dir));
+        config.copy_settings(&self.get());
+        self.replace(config)
+    }
+}
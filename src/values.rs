@@ -16,6 +16,15 @@ pub const TYPE_FLOAT: u16 = 7;
 pub const TYPE_STR: u16 = 8;
 pub const TYPE_RAW: u16 = 9;
 pub const TYPE_MAP: u16 = 10;
+pub const TYPE_U64: u16 = 11;
+pub const TYPE_I64: u16 = 12;
+pub const TYPE_F32: u16 = 13;
+pub const TYPE_F64: u16 = 14;
+pub const TYPE_BOOL: u16 = 15;
+/// the head type of "str" and "raw" longer than u16, the len is u32,
+/// it is only on the wire, the value is still Value::Str and Value::Raw
+pub const TYPE_LONG_STR: u16 = 16;
+pub const TYPE_LONG_RAW: u16 = 17;
 pub const TYPE_AU8: u16 = 21;
 pub const TYPE_AI8: u16 = 22;
 pub const TYPE_AU16: u16 = 23;
@@ -26,6 +35,17 @@ pub const TYPE_AFLOAT: u16 = 27;
 pub const TYPE_ASTR: u16 = 28;
 pub const TYPE_ARAW: u16 = 29;
 pub const TYPE_AMAP: u16 = 30;
+pub const TYPE_AU64: u16 = 31;
+pub const TYPE_AI64: u16 = 32;
+pub const TYPE_AF32: u16 = 33;
+pub const TYPE_AF64: u16 = 34;
+pub const TYPE_ABOOL: u16 = 35;
+
+/// "float" is stored as i32 of value multiply this scale by default
+pub const DEFAULT_FLOAT_SCALE: u32 = 1000;
+
+/// the proto arg is null if its head is (NULL_ARG_INDEX, nil), (0, nil) ends the args
+pub const NULL_ARG_INDEX: u16 = 1;
 
 pub const STR_TYPE_NIL: &'static str = "nil";
 pub const STR_TYPE_U8: &'static str = "u8";
@@ -38,6 +58,13 @@ pub const STR_TYPE_FLOAT: &'static str = "float";
 pub const STR_TYPE_STR: &'static str = "str";
 pub const STR_TYPE_RAW: &'static str = "raw";
 pub const STR_TYPE_MAP: &'static str = "map";
+pub const STR_TYPE_U64: &'static str = "u64";
+pub const STR_TYPE_I64: &'static str = "i64";
+pub const STR_TYPE_F32: &'static str = "f32";
+pub const STR_TYPE_F64: &'static str = "f64";
+pub const STR_TYPE_BOOL: &'static str = "bool";
+pub const STR_TYPE_LONG_STR: &'static str = "long_str";
+pub const STR_TYPE_LONG_RAW: &'static str = "long_raw";
 pub const STR_TYPE_AU8: &'static str = "u8[]";
 pub const STR_TYPE_AI8: &'static str = "i8[]";
 pub const STR_TYPE_AU16: &'static str = "u16[]";
@@ -48,6 +75,15 @@ pub const STR_TYPE_AFLOAT: &'static str = "float[]";
 pub const STR_TYPE_ASTR: &'static str = "str[]";
 pub const STR_TYPE_ARAW: &'static str = "raw[]";
 pub const STR_TYPE_AMAP: &'static str = "map[]";
+pub const STR_TYPE_AU64: &'static str = "u64[]";
+pub const STR_TYPE_AI64: &'static str = "i64[]";
+pub const STR_TYPE_AF32: &'static str = "f32[]";
+pub const STR_TYPE_AF64: &'static str = "f64[]";
+pub const STR_TYPE_ABOOL: &'static str = "bool[]";
+
+/// the other spellings of type names used in documents, (alias, name)
+pub const TYPE_NAME_ALIASES: &'static [(&'static str, &'static str)] =
+    &[("none", STR_TYPE_NIL), ("string", STR_TYPE_STR), ("string[]", STR_TYPE_ASTR)];
 
 #[derive(PartialEq, Clone)]
 pub enum Value {
@@ -62,6 +98,11 @@ pub enum Value {
     Str(String),
     Raw(Vec<u8>),
     Map(HashMap<String, Value>),
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
     AU8(Vec<Value>),
     AI8(Vec<Value>),
     AU16(Vec<Value>),
@@ -72,6 +113,13 @@ pub enum Value {
     AStr(Vec<Value>),
     ARaw(Vec<Value>),
     AMap(Vec<Value>),
+    AU64(Vec<Value>),
+    AI64(Vec<Value>),
+    AF32(Vec<Value>),
+    AF64(Vec<Value>),
+    ABool(Vec<Value>),
+    /// the array of arrays like "u32[][]", with the type of the elements like TYPE_AU32
+    AArray(u16, Vec<Value>),
 }
 
 impl fmt::Debug for Value {
@@ -88,6 +136,11 @@ impl fmt::Debug for Value {
             Value::Str(ref val) => write!(fmt, "str({:?})", val),
             Value::Raw(ref val) => write!(fmt, "raw({:?})", val),
             Value::Map(ref val) => write!(fmt, "str({:?})", val),
+            Value::U64(val) => write!(fmt, "u64({:?})", val),
+            Value::I64(val) => write!(fmt, "i64({:?})", val),
+            Value::F32(val) => write!(fmt, "f32({:?})", val),
+            Value::F64(val) => write!(fmt, "f64({:?})", val),
+            Value::Bool(val) => write!(fmt, "bool({:?})", val),
             Value::AU8(ref val) => write!(fmt, "AU8({:?})", val),
             Value::AI8(ref val) => write!(fmt, "AI8({:?})", val),
             Value::AU16(ref val) => write!(fmt, "AU16({:?})", val),
@@ -98,6 +151,14 @@ impl fmt::Debug for Value {
             Value::AStr(ref val) => write!(fmt, "AStr({:?})", val),
             Value::ARaw(ref val) => write!(fmt, "ARaw({:?})", val),
             Value::AMap(ref val) => write!(fmt, "AMap({:?})", val),
+            Value::AU64(ref val) => write!(fmt, "AU64({:?})", val),
+            Value::AI64(ref val) => write!(fmt, "AI64({:?})", val),
+            Value::AF32(ref val) => write!(fmt, "AF32({:?})", val),
+            Value::AF64(ref val) => write!(fmt, "AF64({:?})", val),
+            Value::ABool(ref val) => write!(fmt, "ABool({:?})", val),
+            Value::AArray(t, ref val) => {
+                write!(fmt, "AArray({}, {:?})", get_pattern_by_type(t), val)
+            }
         }
     }
 }
@@ -138,12 +199,36 @@ impl From<i32> for Value {
     }
 }
 
+impl From<u64> for Value {
+    fn from(val: u64) -> Value {
+        Value::U64(val)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(val: i64) -> Value {
+        Value::I64(val)
+    }
+}
+
 impl From<f32> for Value {
     fn from(val: f32) -> Value {
         Value::Float(val)
     }
 }
 
+impl From<f64> for Value {
+    fn from(val: f64) -> Value {
+        Value::F64(val)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(val: bool) -> Value {
+        Value::Bool(val)
+    }
+}
+
 impl From<String> for Value {
     fn from(val: String) -> Value {
         Value::Str(val)
@@ -216,10 +301,47 @@ impl Into<i32> for Value {
     }
 }
 
+impl Into<u64> for Value {
+    fn into(self) -> u64 {
+        match self {
+            Value::U64(val) => val,
+            _ => panic!("into error"),
+        }
+    }
+}
+
+impl Into<i64> for Value {
+    fn into(self) -> i64 {
+        match self {
+            Value::I64(val) => val,
+            _ => panic!("into error"),
+        }
+    }
+}
+
 impl Into<f32> for Value {
     fn into(self) -> f32 {
         match self {
             Value::Float(val) => val,
+            Value::F32(val) => val,
+            _ => panic!("into error"),
+        }
+    }
+}
+
+impl Into<f64> for Value {
+    fn into(self) -> f64 {
+        match self {
+            Value::F64(val) => val,
+            _ => panic!("into error"),
+        }
+    }
+}
+
+impl Into<bool> for Value {
+    fn into(self) -> bool {
+        match self {
+            Value::Bool(val) => val,
             _ => panic!("into error"),
         }
     }
@@ -264,10 +386,120 @@ pub fn get_array_contains_type(value: &Value) -> u16 {
         Value::AStr(_) => TYPE_STR,
         Value::ARaw(_) => TYPE_RAW,
         Value::AMap(_) => TYPE_MAP,
+        Value::AU64(_) => TYPE_U64,
+        Value::AI64(_) => TYPE_I64,
+        Value::AF32(_) => TYPE_F32,
+        Value::AF64(_) => TYPE_F64,
+        Value::ABool(_) => TYPE_BOOL,
+        Value::AArray(t, _) => t,
         _ => TYPE_NIL,
     }
 }
 
+pub fn get_array_values(value: &Value) -> Option<&Vec<Value>> {
+    match *value {
+        Value::AU8(ref val) |
+        Value::AI8(ref val) |
+        Value::AU16(ref val) |
+        Value::AI16(ref val) |
+        Value::AU32(ref val) |
+        Value::AI32(ref val) |
+        Value::AFloat(ref val) |
+        Value::AStr(ref val) |
+        Value::ARaw(ref val) |
+        Value::AMap(ref val) |
+        Value::AU64(ref val) |
+        Value::AI64(ref val) |
+        Value::AF32(ref val) |
+        Value::AF64(ref val) |
+        Value::ABool(ref val) |
+        Value::AArray(_, ref val) => Some(val),
+        _ => None,
+    }
+}
+
+/// build the array value of the array type, return nil if the type is not array
+pub fn new_array_value(t: u16, val: Vec<Value>) -> Value {
+    match t {
+        TYPE_AU8 => Value::AU8(val),
+        TYPE_AI8 => Value::AI8(val),
+        TYPE_AU16 => Value::AU16(val),
+        TYPE_AI16 => Value::AI16(val),
+        TYPE_AU32 => Value::AU32(val),
+        TYPE_AI32 => Value::AI32(val),
+        TYPE_AFLOAT => Value::AFloat(val),
+        TYPE_ASTR => Value::AStr(val),
+        TYPE_ARAW => Value::ARaw(val),
+        TYPE_AMAP => Value::AMap(val),
+        TYPE_AU64 => Value::AU64(val),
+        TYPE_AI64 => Value::AI64(val),
+        TYPE_AF32 => Value::AF32(val),
+        TYPE_AF64 => Value::AF64(val),
+        TYPE_ABOOL => Value::ABool(val),
+        t if is_nested_array_type(t) => Value::AArray(t - TYPE_STEP, val),
+        _ => Value::Nil,
+    }
+}
+
+/// convert the number to the wider number type without loss, such as u8 to u16 or i32 to i64,
+/// arrays are converted element by element, return None if it can't be converted
+pub fn coerce_number(value: &Value, t: u16) -> Option<Value> {
+    if let Some(val) = get_array_values(value) {
+        if t <= TYPE_STEP {
+            return None;
+        }
+        let mut array = vec![];
+        for v in val {
+            array.push(unwrap_or!(coerce_number(v, t - TYPE_STEP), return None));
+        }
+        return match new_array_value(t, array) {
+            Value::Nil => None,
+            array => Some(array),
+        };
+    }
+    // (is signed, bits, value)
+    let (signed, bits, val) = match *value {
+        Value::U8(val) => (false, 8, val as i64),
+        Value::I8(val) => (true, 8, val as i64),
+        Value::U16(val) => (false, 16, val as i64),
+        Value::I16(val) => (true, 16, val as i64),
+        Value::U32(val) => (false, 32, val as i64),
+        Value::I32(val) => (true, 32, val as i64),
+        Value::U64(val) if t == TYPE_U64 => return Some(Value::U64(val)),
+        Value::I64(val) if t == TYPE_I64 => return Some(Value::I64(val)),
+        Value::F32(val) if t == TYPE_F64 => return Some(Value::F64(val as f64)),
+        _ => return None,
+    };
+    let (to_signed, to_bits) = match t {
+        TYPE_U8 => (false, 8),
+        TYPE_I8 => (true, 8),
+        TYPE_U16 => (false, 16),
+        TYPE_I16 => (true, 16),
+        TYPE_U32 => (false, 32),
+        TYPE_I32 => (true, 32),
+        TYPE_U64 => (false, 64),
+        TYPE_I64 => (true, 64),
+        _ => return None,
+    };
+    let vailed = match (signed, to_signed) {
+        (false, false) | (true, true) => to_bits >= bits,
+        (false, true) => to_bits > bits,
+        (true, false) => false,
+    };
+    if !vailed {
+        return None;
+    }
+    Some(match t {
+        TYPE_U8 => Value::U8(val as u8),
+        TYPE_I8 => Value::I8(val as i8),
+        TYPE_U16 => Value::U16(val as u16),
+        TYPE_I16 => Value::I16(val as i16),
+        TYPE_U32 => Value::U32(val as u32),
+        TYPE_I32 => Value::I32(val as i32),
+        TYPE_U64 => Value::U64(val as u64),
+        _ => Value::I64(val),
+    })
+}
 
 pub fn get_type_by_value(value: &Value) -> u16 {
     match *value {
@@ -281,6 +513,11 @@ pub fn get_type_by_value(value: &Value) -> u16 {
         Value::Str(_) => TYPE_STR,
         Value::Raw(_) => TYPE_RAW,
         Value::Map(_) => TYPE_MAP,
+        Value::U64(_) => TYPE_U64,
+        Value::I64(_) => TYPE_I64,
+        Value::F32(_) => TYPE_F32,
+        Value::F64(_) => TYPE_F64,
+        Value::Bool(_) => TYPE_BOOL,
         Value::AU8(_) => TYPE_AU8,
         Value::AI8(_) => TYPE_AI8,
         Value::AU16(_) => TYPE_AU16,
@@ -291,6 +528,12 @@ pub fn get_type_by_value(value: &Value) -> u16 {
         Value::AStr(_) => TYPE_ASTR,
         Value::ARaw(_) => TYPE_ARAW,
         Value::AMap(_) => TYPE_AMAP,
+        Value::AU64(_) => TYPE_AU64,
+        Value::AI64(_) => TYPE_AI64,
+        Value::AF32(_) => TYPE_AF32,
+        Value::AF64(_) => TYPE_AF64,
+        Value::ABool(_) => TYPE_ABOOL,
+        Value::AArray(t, _) => t + TYPE_STEP,
         _ => TYPE_NIL,
     }
 }
@@ -308,6 +551,13 @@ pub fn get_type_by_name(name: &str) -> u16 {
         STR_TYPE_STR => TYPE_STR,
         STR_TYPE_RAW => TYPE_RAW,
         STR_TYPE_MAP => TYPE_MAP,
+        STR_TYPE_U64 => TYPE_U64,
+        STR_TYPE_I64 => TYPE_I64,
+        STR_TYPE_F32 => TYPE_F32,
+        STR_TYPE_F64 => TYPE_F64,
+        STR_TYPE_BOOL => TYPE_BOOL,
+        STR_TYPE_LONG_STR => TYPE_LONG_STR,
+        STR_TYPE_LONG_RAW => TYPE_LONG_RAW,
         STR_TYPE_AU8 => TYPE_AU8,
         STR_TYPE_AI8 => TYPE_AI8,
         STR_TYPE_AU16 => TYPE_AU16,
@@ -318,10 +568,145 @@ pub fn get_type_by_name(name: &str) -> u16 {
         STR_TYPE_ASTR => TYPE_ASTR,
         STR_TYPE_ARAW => TYPE_ARAW,
         STR_TYPE_AMAP => TYPE_AMAP,
-        _ => TYPE_NIL,
+        STR_TYPE_AU64 => TYPE_AU64,
+        STR_TYPE_AI64 => TYPE_AI64,
+        STR_TYPE_AF32 => TYPE_AF32,
+        STR_TYPE_AF64 => TYPE_AF64,
+        STR_TYPE_ABOOL => TYPE_ABOOL,
+        _ => {
+            if let Some(name) = get_alias_name(name) {
+                return get_type_by_name(name);
+            }
+            if let Some(base) = get_optional_base(name) {
+                return get_type_by_name(base);
+            }
+            // the array of arrays like "u32[][]"
+            if name.ends_with("[][]") {
+                return match get_type_by_name(&name[..name.len() - 2]) {
+                    TYPE_NIL => TYPE_NIL,
+                    t => t + TYPE_STEP,
+                };
+            }
+            let is_array = name.ends_with("[]");
+            if get_schema_name(name).is_some() {
+                return if is_array { TYPE_AMAP } else { TYPE_MAP };
+            }
+            if get_enum_name(name).is_some() {
+                return if is_array { TYPE_AU16 } else { TYPE_U16 };
+            }
+            TYPE_NIL
+        }
+    }
+}
+
+/// the schema name of the pattern like "map<PlayerInfo>" or "map<PlayerInfo>[]"
+pub fn get_schema_name(pattern: &str) -> Option<&str> {
+    get_pattern_param(pattern, "map")
+}
+
+/// the enum name of the pattern like "enum<Quality>" or "enum<Quality>[]",
+/// the enum value is encoded as u16
+pub fn get_enum_name(pattern: &str) -> Option<&str> {
+    get_pattern_param(pattern, "enum")
+}
+
+/// the pattern without "?" of the optional proto arg like "u32?", None if it is not optional
+pub fn get_optional_base(pattern: &str) -> Option<&str> {
+    if !pattern.ends_with("?") {
+        return None;
+    }
+    let base = &pattern[..pattern.len() - 1];
+    if base.is_empty() || base.ends_with("?") {
+        None
+    } else {
+        Some(base)
+    }
+}
+
+fn get_pattern_param<'a>(pattern: &'a str, kind: &str) -> Option<&'a str> {
+    let mut pattern = get_optional_base(pattern).unwrap_or(pattern);
+    while pattern.ends_with("[]") {
+        pattern = &pattern[..pattern.len() - 2];
+    }
+    if !pattern.starts_with(kind) || !pattern.ends_with(">") {
+        return None;
+    }
+    let param = &pattern[kind.len()..pattern.len() - 1];
+    if param.len() > 1 && param.starts_with("<") {
+        Some(&param[1..])
+    } else {
+        None
+    }
+}
+
+/// the pattern with the type name instead of alias, the schema and enum pattern is kept,
+/// return None if it is unknown
+pub fn get_canonical_pattern(pattern: &str) -> Option<String> {
+    if let Some(base) = get_optional_base(pattern) {
+        return get_canonical_pattern(base).map(|base| base + "?");
+    }
+    if pattern.ends_with("[][]") {
+        return get_canonical_pattern(&pattern[..pattern.len() - 2]).map(|base| base + "[]");
+    }
+    if get_schema_name(pattern).is_some() || get_enum_name(pattern).is_some() {
+        return Some(pattern.to_string());
+    }
+    get_canonical_name(pattern).map(|name| name.to_string())
+}
+
+/// the type name of the alias, such as "str" of "string"
+pub fn get_alias_name(alias: &str) -> Option<&'static str> {
+    TYPE_NAME_ALIASES.iter().find(|&&(a, _)| a == alias).map(|&(_, name)| name)
+}
+
+/// the type name of the name or alias, return None if it is unknown
+pub fn get_canonical_name(name: &str) -> Option<&'static str> {
+    match get_type_by_name(name) {
+        TYPE_NIL if name != STR_TYPE_NIL && get_alias_name(name) != Some(STR_TYPE_NIL) => None,
+        t if is_nested_array_type(t) => None,
+        t => Some(get_name_by_type(t)),
+    }
+}
+
+/// the type known by the decoders before "u64", the map field of other types is wrapped
+/// as "raw" so that the old decoders can skip it
+pub fn is_legacy_type(t: u16) -> bool {
+    t <= TYPE_MAP || (t >= TYPE_AU8 && t <= TYPE_AMAP)
+}
+
+/// the array type like "u32[]" or "u32[][]", the element type of Value::AArray must be it
+pub fn is_array_type(t: u16) -> bool {
+    (t >= TYPE_AU8 && t <= TYPE_ABOOL) || is_nested_array_type(t)
+}
+
+/// the element type of the first Value::AArray at any level whose element is not array,
+/// such as AArray(TYPE_U32, ..) which must be AU32
+pub fn find_bad_array_element(value: &Value) -> Option<u16> {
+    match *value {
+        Value::AArray(t, _) if !is_array_type(t) => Some(t),
+        _ => {
+            get_array_values(value)
+                .and_then(|val| val.iter().filter_map(find_bad_array_element).next())
+        }
+    }
+}
+
+/// the array of arrays type like "u32[][]", whose id is the element type id add TYPE_STEP
+pub fn is_nested_array_type(t: u16) -> bool {
+    t > TYPE_STEP * 2 && t % TYPE_STEP != TYPE_NIL && t % TYPE_STEP <= TYPE_BOOL
+}
+
+/// the pattern of the type, contains the array of arrays like "u32[][]"
+pub fn get_pattern_by_type(t: u16) -> String {
+    if is_nested_array_type(t) {
+        get_pattern_by_type(t - TYPE_STEP) + "[]"
+    } else {
+        get_name_by_type(t).to_string()
     }
 }
 
+/// the type name of the base and array types, "nil" for the array of arrays,
+/// see get_pattern_by_type
 pub fn get_name_by_type(index: u16) -> &'static str {
     match index {
         TYPE_NIL => STR_TYPE_NIL,
@@ -335,6 +720,13 @@ pub fn get_name_by_type(index: u16) -> &'static str {
         TYPE_STR => STR_TYPE_STR,
         TYPE_RAW => STR_TYPE_RAW,
         TYPE_MAP => STR_TYPE_MAP,
+        TYPE_U64 => STR_TYPE_U64,
+        TYPE_I64 => STR_TYPE_I64,
+        TYPE_F32 => STR_TYPE_F32,
+        TYPE_F64 => STR_TYPE_F64,
+        TYPE_BOOL => STR_TYPE_BOOL,
+        TYPE_LONG_STR => STR_TYPE_LONG_STR,
+        TYPE_LONG_RAW => STR_TYPE_LONG_RAW,
         TYPE_AU8 => STR_TYPE_AU8,
         TYPE_AI8 => STR_TYPE_AI8,
         TYPE_AU16 => STR_TYPE_AU16,
@@ -345,6 +737,11 @@ pub fn get_name_by_type(index: u16) -> &'static str {
         TYPE_ASTR => STR_TYPE_ASTR,
         TYPE_ARAW => STR_TYPE_ARAW,
         TYPE_AMAP => STR_TYPE_AMAP,
+        TYPE_AU64 => STR_TYPE_AU64,
+        TYPE_AI64 => STR_TYPE_AI64,
+        TYPE_AF32 => STR_TYPE_AF32,
+        TYPE_AF64 => STR_TYPE_AF64,
+        TYPE_ABOOL => STR_TYPE_ABOOL,
         _ => STR_TYPE_NIL,
     }
 }
@@ -363,6 +760,12 @@ pub enum ErrorKind {
     MissingError,
     /// string format must be utf-8
     StringFormatError,
+    /// the value can't be represented by the wire type
+    OverflowError,
+    /// the config can't be parsed or is invalid
+    ConfigError,
+    /// the proto is sent in the wrong direction
+    DirectionError,
     /// This kind is returned if the redis error is one that is
     /// not native to the system.  This is usually the case if
     /// the cause is another error.
@@ -490,6 +893,9 @@ impl RpError {
             ErrorKind::ParseError => "parse error",
             ErrorKind::MissingError => "missing error",
             ErrorKind::StringFormatError => "string format error",
+            ErrorKind::OverflowError => "overflow error",
+            ErrorKind::ConfigError => "config error",
+            ErrorKind::DirectionError => "direction error",
             ErrorKind::IoError => "I/O error",
             ErrorKind::ExtensionError => "extension error",
         }
@@ -248,4 +248,1017 @@ fn test_base_proto() {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_encode_u64() {
+    let config = Config::new_empty();
+    let mut buffer = Buffer::new();
+    let value = Value::U64(0x123456789abcdef0 as u64);
+    td_rp::encode_field(&mut buffer, &config, &value).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::I64(-1234567890123)).unwrap();
+
+    // first read field
+    test_head_field(&mut buffer, 0, td_rp::TYPE_U64);
+    // after index type is data
+    let data: &mut [u8; 8] = &mut [0; 8];
+    let size = buffer.read(data).unwrap();
+    assert_eq!(size, 8);
+    assert_eq!(*data, [0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]);
+
+    // second read field
+    let read = td_rp::decode_field(&mut buffer, &config).unwrap();
+    match read {
+        Value::I64(val) => assert_eq!(val, -1234567890123),
+        _ => unreachable!("it will not read"),
+    }
+    let size = buffer.read(data).unwrap();
+    assert_eq!(size, 0);
+}
+
+#[test]
+fn test_encode_map_skip_u64() {
+    let new_config = td_rp::Config::new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" }, \
+                                            \"uid\" : { \"index\" :    2, \"pattern\" : \"u64\" },  \
+                                            \"uids\" : { \"index\" :    3, \"pattern\" : \"i64[]\" }   }",
+        "{}").unwrap();
+    let old_config = td_rp::Config::new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } }",
+        "{}").unwrap();
+    let mut hash_value = HashMap::<String, Value>::new();
+    hash_value.insert("name".to_string(), Value::Str("tickdream".to_string()));
+    hash_value.insert("uid".to_string(), Value::U64(u64::max_value()));
+    hash_value.insert("uids".to_string(), Value::AI64(vec![Value::I64(i64::min_value()), Value::I64(1)]));
+
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &new_config, &Value::Map(hash_value.clone())).unwrap();
+    let read = td_rp::decode_field(&mut buffer, &new_config).unwrap();
+    assert_eq!(read, Value::Map(hash_value.clone()));
+
+    // the protocol without the 64-bit fields drops them, the decoder still knows the types
+    buffer.set_rpos(0);
+    let read = td_rp::decode_field(&mut buffer, &old_config).unwrap();
+    let mut old_value = HashMap::<String, Value>::new();
+    old_value.insert("name".to_string(), Value::Str("tickdream".to_string()));
+    assert_eq!(read, Value::Map(old_value));
+
+    // the decoder built before u64 reads every value as the old types, the 64-bit ones as raw
+    buffer.set_rpos(0);
+    test_head_field(&mut buffer, 0, td_rp::TYPE_MAP);
+    loop {
+        let index: u16 = td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap().into();
+        let _: u16 = td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap().into();
+        if index == 0 {
+            break;
+        }
+        let _: u16 = td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap().into();
+        let t: u16 = td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap().into();
+        assert!(t == td_rp::TYPE_STR || (index > 1 && t == td_rp::TYPE_RAW));
+        td_rp::decode_str_raw(&mut buffer, t).unwrap();
+    }
+    assert_eq!(buffer.get_rpos(), buffer.len());
+
+    // the unknown type can't be skipped, it is not read as nil
+    let mut buffer = Buffer::new();
+    for v in &[0, td_rp::TYPE_MAP, 1, 18] {
+        td_rp::encode_number(&mut buffer, &Value::U16(*v)).unwrap();
+    }
+    td_rp::encode_number(&mut buffer, &Value::U32(7)).unwrap();
+    let err = td_rp::decode_field(&mut buffer, &new_config).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+    assert_eq!(err.extension_error_detail(), Some("unknown type 18"));
+}
+
+#[test]
+fn test_encode_f32_f64() {
+    let config = Config::new_empty();
+    let mut buffer = Buffer::new();
+    let small = 0.000123456789f32;
+    let big = 123456789.123456789f64;
+    td_rp::encode_field(&mut buffer, &config, &Value::F32(small)).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::F64(big)).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::AF64(vec![Value::F64(-0.0), Value::F64(1e300)])).unwrap();
+
+    // first read field
+    test_head_field(&mut buffer, 0, td_rp::TYPE_F32);
+    let data: &mut [u8; 4] = &mut [0; 4];
+    let size = buffer.read(data).unwrap();
+    assert_eq!(size, 4);
+    assert_eq!(u32::from_le(unsafe { mem::transmute::<[u8;4], u32>(*data) }), small.to_bits());
+
+    // second read field, bit exact
+    match td_rp::decode_field(&mut buffer, &config).unwrap() {
+        Value::F64(val) => assert_eq!(val.to_bits(), big.to_bits()),
+        _ => unreachable!("it will not read"),
+    }
+    match td_rp::decode_field(&mut buffer, &config).unwrap() {
+        Value::AF64(ref val) => {
+            assert_eq!(val.len(), 2);
+            match val[0] {
+                Value::F64(v) => assert_eq!(v.to_bits(), (-0.0f64).to_bits()),
+                _ => unreachable!("it will not read"),
+            }
+            assert_eq!(val[1], Value::F64(1e300));
+        }
+        _ => unreachable!("it will not read"),
+    }
+}
+
+#[test]
+fn test_encode_float_scale() {
+    let config = td_rp::Config::new(" { \"price\" : { \"index\" :    1, \"pattern\" : \"float\", \"scale\" : 100 }, \
+                                        \"rate\" : { \"index\" :    2, \"pattern\" : \"float\" } }",
+        "{}");
+    let mut config = config.unwrap();
+    config.set_float_scale(1000000).unwrap();
+    let mut hash_value = HashMap::<String, Value>::new();
+    hash_value.insert("price".to_string(), Value::Float(9999999.5));
+    hash_value.insert("rate".to_string(), Value::Float(0.123456));
+
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &config, &Value::Map(hash_value.clone())).unwrap();
+    let read = td_rp::decode_field(&mut buffer, &config).unwrap();
+    assert_eq!(read, Value::Map(hash_value));
+
+    // the config scale is used outside map
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &config, &Value::Float(1.5)).unwrap();
+    test_head_field(&mut buffer, 0, td_rp::TYPE_FLOAT);
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_I32).unwrap(), Value::I32(1500000));
+
+    // the scaled value is truncated as the old encoder
+    let mut buffer = Buffer::new();
+    td_rp::encode_float(&mut buffer, 0.0019, 1000).unwrap();
+    td_rp::encode_float(&mut buffer, -0.0019, 1000).unwrap();
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_I32).unwrap(), Value::I32(1));
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_I32).unwrap(), Value::I32(-1));
+
+    let err = config.set_float_scale(0).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(config.get_float_scale(), 1000000);
+}
+
+#[test]
+fn test_encode_float_overflow() {
+    let config = Config::new_empty();
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_field(&mut buffer, &config, &Value::Float(3000000.0)).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::OverflowError);
+    let err = td_rp::encode_float(&mut buffer, ::std::f32::NAN, 1000).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::OverflowError);
+    td_rp::encode_float(&mut buffer, 3000000.0, 100).unwrap();
+}
+
+#[test]
+fn test_encode_bool() {
+    let config = Config::new_empty();
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &config, &Value::Bool(true)).unwrap();
+    let array = vec![Value::Bool(false), Value::Bool(true)];
+    td_rp::encode_field(&mut buffer, &config, &Value::ABool(array.clone())).unwrap();
+
+    test_head_field(&mut buffer, 0, td_rp::TYPE_BOOL);
+    let data: &mut [u8; 1] = &mut [0];
+    let size = buffer.read(data).unwrap();
+    assert_eq!(size, 1);
+    assert_eq!(data[0], 1);
+
+    let read = td_rp::decode_field(&mut buffer, &config).unwrap();
+    assert_eq!(read, Value::ABool(array));
+
+    // only 0 and 1 is vailed
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &config, &Value::U8(2)).unwrap();
+    buffer.set_wpos(2);
+    td_rp::encode_number(&mut buffer, &Value::U16(td_rp::TYPE_BOOL)).unwrap();
+    buffer.set_rpos(0);
+    let err = td_rp::decode_field(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ParseError);
+}
+
+#[test]
+fn test_encode_long_str() {
+    let config = Config::new_empty();
+    let name: String = ::std::iter::repeat("tickdream").take(10000).collect();
+    let raw = vec![7u8; 0xFFFF];
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &config, &Value::Str(name.clone())).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::Raw(raw.clone())).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::Str("short".to_string())).unwrap();
+
+    // the long str has its own type with u32 len, the 65535 bytes raw is still u16 len
+    test_head_field(&mut buffer, 0, td_rp::TYPE_LONG_STR);
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_U32).unwrap(), Value::U32(90000));
+    buffer.set_rpos(buffer.get_rpos() + 90000);
+    test_head_field(&mut buffer, 0, td_rp::TYPE_RAW);
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap(), Value::U16(0xFFFF));
+    buffer.set_rpos(0);
+
+    assert_eq!(td_rp::decode_field(&mut buffer, &config).unwrap(), Value::Str(name.clone()));
+    assert_eq!(td_rp::decode_field(&mut buffer, &config).unwrap(), Value::Raw(raw));
+    assert_eq!(td_rp::decode_field(&mut buffer, &config).unwrap(), Value::Str("short".to_string()));
+
+    // the str of 65535 bytes written by the old encoder
+    let old: String = ::std::iter::repeat("a").take(0xFFFF).collect();
+    let mut buffer = Buffer::new();
+    td_rp::encode_number(&mut buffer, &Value::U16(0xFFFF)).unwrap();
+    buffer.write(old.as_bytes()).unwrap();
+    assert_eq!(td_rp::decode_str_raw(&mut buffer, td_rp::TYPE_STR).unwrap(), Value::Str(old));
+
+    // the str without head can't be longer than u16
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_str_raw(&mut buffer, &Value::Str(name)).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::OverflowError);
+
+    // the len more than the left bytes fails before allocating
+    let mut buffer = Buffer::new();
+    td_rp::encode_number(&mut buffer, &Value::U32(u32::max_value())).unwrap();
+    let err = td_rp::decode_str_raw(&mut buffer, td_rp::TYPE_LONG_RAW).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::NoLeftSpaceError);
+}
+
+#[test]
+fn test_encode_proto_args_not_match() {
+    let config = td_rp::Config::new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } }",
+        "{\"cmd_test_op\"        : { \"msg_type\" :    \"server\", \"args\" : [ \"map\", \"u32[]\" ] }}");
+    let config = config.unwrap();
+    let name = "cmd_test_op".to_string();
+
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::U16(1), Value::AU32(vec![])]).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_test_op arg 0 expect map but u16"));
+    assert_eq!(buffer.len(), 0);
+
+    let array = Value::AU32(vec![Value::U32(1), Value::U16(2)]);
+    let err = td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::Map(HashMap::new()), array]).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_test_op arg 1 element 1 expect u32 but u16"));
+    assert_eq!(buffer.len(), 0);
+
+    let array = Value::AU32(vec![Value::U32(1), Value::U32(2)]);
+    td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::Map(HashMap::new()), array.clone()]).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, vec![Value::Map(HashMap::new()), array]);
+}
+
+#[test]
+fn test_decode_proto_args_not_match() {
+    let config = td_rp::Config::new("{}",
+        "{\"cmd_test_op\"        : { \"msg_type\" :    \"server\", \"args\" : [ \"u16\", \"i64[]\" ] }}");
+    let mut config = config.unwrap();
+
+    // the proto is encoded by other side with narrower numbers
+    let mut buffer = Buffer::new();
+    td_rp::encode_str_raw(&mut buffer, &Value::Str("cmd_test_op".to_string())).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::U8(200)).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::AI8(vec![Value::I8(-1), Value::I8(2)])).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::Nil).unwrap();
+
+    let err = td_rp::decode_proto(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_test_op arg 0 expect u16 but u8"));
+
+    config.set_coerce_number(true);
+    buffer.set_rpos(0);
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, vec![Value::U16(200), Value::AI64(vec![Value::I64(-1), Value::I64(2)])]);
+
+    // signed can't be coerced to unsigned
+    let mut buffer = Buffer::new();
+    td_rp::encode_str_raw(&mut buffer, &Value::Str("cmd_test_op".to_string())).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::I8(1)).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::AI64(vec![])).unwrap();
+    td_rp::encode_field(&mut buffer, &config, &Value::Nil).unwrap();
+    let err = td_rp::decode_proto(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_test_op arg 0 expect u16 but i8"));
+}
+
+#[test]
+fn test_encode_map_pattern_not_match() {
+    let config = td_rp::Config::new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" }, \
+                                        \"index\" : { \"index\" :    2, \"pattern\" : \"u16\" } }",
+        "{}");
+    let mut config = config.unwrap();
+    let mut hash_value = HashMap::<String, Value>::new();
+    hash_value.insert("name".to_string(), Value::Str("tickdream".to_string()));
+    hash_value.insert("index".to_string(), Value::U32(1));
+
+    // default ignore the mismatch
+    let mut buffer = Buffer::new();
+    let mut report = td_rp::Report::new();
+    let value = Value::Map(hash_value.clone());
+    td_rp::encode_field_collect(&mut buffer, &config, &value, &mut report).unwrap();
+    assert_eq!(td_rp::decode_field_collect(&mut buffer, &config, &mut report).unwrap(), value);
+    assert!(report.issues.is_empty());
+
+    // collect the mismatch both encode and decode
+    config.set_mismatch_policy(td_rp::MismatchPolicy::Collect);
+    let mut buffer = Buffer::new();
+    let mut report = td_rp::Report::new();
+    td_rp::encode_field_collect(&mut buffer, &config, &value, &mut report).unwrap();
+    assert_eq!(td_rp::decode_field_collect(&mut buffer, &config, &mut report).unwrap(), value);
+    assert_eq!(report.issues, vec!["field index expect u16 but u32".to_string(),
+                                   "field index expect u16 but u32".to_string()]);
+
+    // the plain calls don't keep the issues
+    let mut buffer = Buffer::new();
+    td_rp::encode_field(&mut buffer, &config, &value).unwrap();
+    assert_eq!(td_rp::decode_field(&mut buffer, &config).unwrap(), value);
+
+    // strict fail on decode
+    config.set_mismatch_policy(td_rp::MismatchPolicy::Strict);
+    buffer.set_rpos(0);
+    let err = td_rp::decode_field(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+    assert_eq!(err.extension_error_detail(), Some("field index expect u16 but u32"));
+
+    // strict fail on encode
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_field(&mut buffer, &config, &Value::Map(hash_value.clone())).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("field index expect u16 but u32"));
+}
+
+#[test]
+fn test_encode_map_unknown_key() {
+    let config = td_rp::Config::new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } }", "{}");
+    let mut config = config.unwrap();
+    let mut hash_value = HashMap::<String, Value>::new();
+    hash_value.insert("name".to_string(), Value::Str("tickdream".to_string()));
+    hash_value.insert("nmae".to_string(), Value::Str("typo".to_string()));
+
+    config.set_unknown_key_policy(td_rp::UnknownKeyPolicy::Error);
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_field(&mut buffer, &config, &Value::Map(hash_value.clone())).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::MissingError);
+    assert_eq!(err.extension_error_detail(), Some("unknown field nmae"));
+
+    config.set_unknown_key_policy(td_rp::UnknownKeyPolicy::Collect);
+    let mut buffer = Buffer::new();
+    let mut report = td_rp::Report::new();
+    td_rp::encode_field_collect(&mut buffer, &config, &Value::Map(hash_value.clone()), &mut report)
+        .unwrap();
+    assert_eq!(report.dropped_keys, vec!["nmae".to_string()]);
+    hash_value.remove("nmae");
+    assert_eq!(td_rp::decode_field(&mut buffer, &config).unwrap(), Value::Map(hash_value));
+}
+
+#[test]
+fn test_proto_by_index() {
+    let config = td_rp::Config::new("{}",
+        "{\"cmd_test_op\"        : { \"index\" :    7, \"msg_type\" :    \"server\", \"args\" : [ \"u16\" ] }}");
+    let mut config = config.unwrap();
+    let name = "cmd_test_op".to_string();
+    config.set_proto_by_index(true);
+
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::U16(1)]).unwrap();
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap(), Value::U16(0));
+    assert_eq!(td_rp::decode_number(&mut buffer, td_rp::TYPE_U16).unwrap(), Value::U16(7));
+    buffer.set_rpos(0);
+    assert_eq!(td_rp::decode_proto(&mut buffer, &config).unwrap(), (name.clone(), vec![Value::U16(1)]));
+
+    // decode can read the name form too
+    config.set_proto_by_index(false);
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::U16(1)]).unwrap();
+    assert_eq!(td_rp::decode_str_raw(&mut buffer, td_rp::TYPE_STR).unwrap(), Value::Str(name.clone()));
+    buffer.set_rpos(0);
+    assert_eq!(td_rp::decode_proto(&mut buffer, &config).unwrap(), (name.clone(), vec![Value::U16(1)]));
+    assert_eq!(config.get_proto_index_name(&7), Some(&name));
+    assert!(config.get_proto_by_index(&8).is_none());
+}
+
+#[test]
+fn test_config_error() {
+    let err = td_rp::Config::try_new_by_full_str("{ \"field\" : { \n \"name\" : { \"index\" 1 } } }").unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(err.extension_error_detail(), Some("expected `:` at line 2 column 22"));
+
+    let err = td_rp::Config::try_new_by_full_str("{ \"field\" : {} }").unwrap_err();
+    assert_eq!(err.to_string(), "missing section: proto");
+
+    let err = td_rp::Config::try_new(" { \"name\" : { \"index\" :    70000, \"pattern\" : \"str\" } }", "{}").unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("field name index must be u16"));
+
+    let err = td_rp::Config::try_new("{}", "{\"cmd_test_op\" : { \"args\" : [ \"map\", 1 ] }}").unwrap_err();
+    assert_eq!(err.extension_error_detail(),
+               Some("proto cmd_test_op arg 1 must be string or object with name and type"));
+    assert!(td_rp::Config::new("{}", "{\"cmd_test_op\" : { \"args\" : [ \"map\", 1 ] }}").is_none());
+
+    let err = td_rp::Config::try_new("{}", "{\"\" : { \"args\" : [ \"u8\" ] }}").unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto name can't be empty"));
+
+    // msg_type is optional
+    let config = td_rp::Config::try_new("{}", "{\"cmd_test_op\" : { \"index\" : 1, \"args\" : [ \"map\" ] }}").unwrap();
+    assert!(config.get_proto_msg_type(&"cmd_test_op".to_string()).is_none());
+}
+
+#[test]
+fn test_config_validate() {
+    let err = td_rp::Config::try_new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" }, \
+                                         \"index\" : { \"index\" :    1, \"pattern\" : \"u16\" },  \
+                                         \"nil\" : { \"index\" :    0, \"pattern\" : \"u8\" },  \
+                                         \"sub_name\" : { \"index\" :    3, \"pattern\" :\"text\" }   }",
+        "{\"cmd_test_op\"        : { \"index\" :    1, \"args\" : [ \"map\", \"u128\" ] }, \
+          \"cmd_test_op2\"        : { \"index\" :    1, \"args\" : [] }}").unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(err.extension_error_detail(),
+               Some("field index and name share index 1; \
+                     field nil index 0 is reserved; \
+                     field sub_name pattern text is unknown; \
+                     proto cmd_test_op arg 1 pattern u128 is unknown; \
+                     proto cmd_test_op and cmd_test_op2 share index 1"));
+
+    let config = td_rp::Config::try_new(" { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } }", "{}").unwrap();
+    assert!(config.validate().is_empty());
+}
+
+#[test]
+fn test_type_name_alias() {
+    assert_eq!(td_rp::get_type_by_name("string"), td_rp::TYPE_STR);
+    assert_eq!(td_rp::get_type_by_name("string[]"), td_rp::TYPE_ASTR);
+    assert_eq!(td_rp::get_type_by_name("none"), td_rp::TYPE_NIL);
+    assert_eq!(td_rp::get_canonical_name("string"), Some("str"));
+    assert_eq!(td_rp::get_canonical_name("none"), Some("nil"));
+    assert_eq!(td_rp::get_canonical_name("text"), None);
+
+    let config = td_rp::Config::try_new_by_full_str("{ \"types\" : [ \"u16\", \"string\", \"map\" ], \
+        \"field\" : { \"name\" : { \"index\" :    1, \"pattern\" : \"string\" }, \
+                      \"index\" : { \"index\" :    2, \"pattern\" : \"u16\" } }, \
+        \"proto\" : { \"cmd_test_op\" : { \"index\" : 1, \"args\" : [ \"map\", \"string\" ] } } }").unwrap();
+    assert_eq!(config.get_field_by_name(&"name".to_string()).unwrap().pattern, "str");
+    assert_eq!(config.get_proto_by_name(&"cmd_test_op".to_string()).unwrap().args, vec!["map", "str"]);
+    assert_eq!(*config.get_types(), vec!["u16", "str", "map"]);
+
+    let err = td_rp::Config::try_new_by_full_str("{ \"types\" : [ \"u16\", \"string\" ], \
+        \"field\" : { \"index\" : { \"index\" :    2, \"pattern\" : \"u32\" } }, \
+        \"proto\" : { \"cmd_test_op\" : { \"args\" : [ \"map\" ] } } }").unwrap_err();
+    assert_eq!(err.extension_error_detail(),
+               Some("field index pattern u32 is not in types; proto cmd_test_op arg 0 pattern map is not in types"));
+
+    let err = td_rp::Config::try_new_by_full_str("{ \"types\" : [ \"u16\", \"text\" ], \"field\" : {}, \"proto\" : {} }").unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("types has unknown type \"text\""));
+}
+
+#[test]
+fn test_doc_protocol() {
+    let mut f = ::std::fs::File::open("doc/protocol.txt").unwrap();
+    let mut s = String::new();
+    f.read_to_string(&mut s).unwrap();
+    let config = td_rp::Config::try_new_by_full_str(&s).unwrap();
+    assert_eq!(config.get_field_by_index(&1).unwrap().pattern, "str");
+}
+
+fn write_protocol_dir(name: &str, files: &[(&str, &str)]) -> ::std::path::PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("td_rp_{}_{}", name, ::std::process::id()));
+    let _ = ::std::fs::remove_dir_all(&dir);
+    ::std::fs::create_dir_all(&dir).unwrap();
+    for &(file, content) in files {
+        let mut f = ::std::fs::File::create(dir.join(file)).unwrap();
+        f.write_all(content.as_bytes()).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_config_from_dir() {
+    let config = td_rp::Config::from_file("doc/protocol.txt").unwrap();
+    assert!(config.get_proto_by_name(&"cmd_achieve_op".to_string()).is_some());
+
+    let dir = write_protocol_dir("split", &[
+        ("a_field.json", "{ \"field\" : { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } } }"),
+        ("b_field.json", "{ \"field\" : { \"index\" : { \"index\" :    2, \"pattern\" : \"u16\" } } }"),
+        ("c_proto.json", "{ \"proto\" : { \"cmd_test_op\" : { \"index\" : 1, \"args\" : [ \"map\" ] } } }"),
+        (".swp", "not a protocol"),
+        ("README.md", "# not a protocol"),
+        ("c_proto.json~", "{ \"proto\" : "),
+    ]);
+    let config = td_rp::Config::from_dir(&dir).unwrap();
+    assert_eq!(config.get_field_index_name(&1), Some(&"name".to_string()));
+    assert_eq!(config.get_field_index_name(&2), Some(&"index".to_string()));
+    assert_eq!(config.get_proto_index_name(&1), Some(&"cmd_test_op".to_string()));
+
+    let dir = write_protocol_dir("conflict", &[
+        ("a.json", "{ \"field\" : { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } } }"),
+        ("b.json", "{ \"field\" : { \"index\" : { \"index\" :    1, \"pattern\" : \"u16\" } } }"),
+    ]);
+    let err = td_rp::Config::from_dir(&dir).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(err.extension_error_detail().unwrap(),
+               format!("field index 1 used in {} and {}", dir.join("a.json").display(), dir.join("b.json").display()));
+
+    let dir = write_protocol_dir("bad", &[("a.json", "{ \"field\" : { ")]);
+    let err = td_rp::Config::from_dir(&dir).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert!(err.to_string().contains("a.json: json syntax error"));
+
+    let err = td_rp::Config::from_file(dir.join("missing.json")).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::IoError);
+}
+
+#[test]
+fn test_config_builder() {
+    let config = td_rp::ConfigBuilder::new()
+        .field("name", 1, "string")
+        .field("index", 2, "u16")
+        .proto("cmd_test_op", 1, &["map", "u8[]"])
+        .build()
+        .unwrap();
+    assert_eq!(config.get_field_by_name(&"name".to_string()).unwrap().pattern, "str");
+    assert_eq!(config.get_field_index_name(&2), Some(&"index".to_string()));
+    assert_eq!(config.get_proto_by_index(&1).unwrap().args, vec!["map", "u8[]"]);
+
+    let mut hash_value = HashMap::<String, Value>::new();
+    hash_value.insert("name".to_string(), Value::Str("tickdream".to_string()));
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_test_op".to_string(),
+                        vec![Value::Map(hash_value.clone()), Value::AU8(vec![])]).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val[0], Value::Map(hash_value));
+
+    // the first problem is returned
+    let err = td_rp::ConfigBuilder::new()
+        .field("name", 1, "str")
+        .field("index", 1, "u16")
+        .field("bad", 0, "u16")
+        .build()
+        .unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(err.extension_error_detail(), Some("field name and index share index 1"));
+
+    let err = td_rp::ConfigBuilder::new().proto("cmd_test_op", 1, &["text"]).build().unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_test_op arg 0 pattern text is unknown"));
+}
+
+#[test]
+fn test_config_to_json() {
+    let config = td_rp::Config::try_new_by_full_str("{ \"float_scale\" : 100, \
+        \"field\" : { \"name\" : { \"index\" :    1, \"pattern\" : \"string\" }, \
+                      \"price\" : { \"index\" :    2, \"pattern\" : \"float\", \"scale\" : 10 } }, \
+        \"proto\" : { \"cmd_test_op\" : { \"index\" : 1, \"msg_type\" : \"server\", \"args\" : [ \"map\" ] } } }").unwrap();
+    let json = config.to_json_string();
+    assert_eq!(json, "{\n  \"field\": {\n    \"name\": {\n      \"index\": 1,\n      \"pattern\": \"str\"\n    },\n    \
+                      \"price\": {\n      \"index\": 2,\n      \"pattern\": \"float\",\n      \"scale\": 10\n    }\n  },\n  \
+                      \"float_scale\": 100,\n  \"proto\": {\n    \"cmd_test_op\": {\n      \"args\": [\n        \"map\"\n      ],\n      \
+                      \"index\": 1,\n      \"msg_type\": \"server\"\n    }\n  }\n}");
+
+    // the output can be loaded again
+    let config = td_rp::Config::try_new_by_full_str(&json).unwrap();
+    assert_eq!(config.to_json_string(), json);
+}
+
+#[test]
+fn test_schema_map() {
+    let mut config = td_rp::Config::try_new_by_full_str("{ \"field\" : { \"name\" : { \"index\" : 1, \"pattern\" : \"str\" } }, \
+        \"schemas\" : { \"PlayerInfo\" : { \"name\" : { \"index\" : 1, \"pattern\" : \"str\" }, \
+                                          \"level\" : { \"index\" : 2, \"pattern\" : \"u8\" } }, \
+                        \"ItemInfo\" : { \"name\" : { \"index\" : 1, \"pattern\" : \"u32\" } } }, \
+        \"proto\" : { \"cmd_enter\" : { \"args\" : [ \"map<PlayerInfo>\", \"map<ItemInfo>[]\", \"map\" ] } } }")
+        .unwrap();
+    let mut player = HashMap::<String, Value>::new();
+    player.insert("name".to_string(), Value::from("tickdream".to_string()));
+    player.insert("level".to_string(), Value::from(10 as u8));
+    let mut item = HashMap::<String, Value>::new();
+    item.insert("name".to_string(), Value::from(1001 as u32));
+    let mut global = HashMap::<String, Value>::new();
+    global.insert("name".to_string(), Value::from("global".to_string()));
+    let args = vec![Value::Map(player),
+                    Value::AMap(vec![Value::Map(item.clone()), Value::Map(item)]),
+                    Value::Map(global.clone())];
+
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_enter".to_string(), args.clone()).unwrap();
+    let (name, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(name, "cmd_enter".to_string());
+    assert_eq!(val, args);
+
+    // the field name of ItemInfo is u32, not the global str
+    config.set_mismatch_policy(td_rp::MismatchPolicy::Strict);
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &"cmd_enter".to_string(),
+                                  vec![args[0].clone(), Value::AMap(vec![Value::Map(global)]), args[2].clone()])
+        .unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("field name expect u32 but str"));
+
+    // the map alone can use the schema too
+    let mut item = HashMap::<String, Value>::new();
+    item.insert("name".to_string(), Value::from(1001 as u32));
+    let mut buffer = Buffer::new();
+    td_rp::encode_map_schema(&mut buffer, &config, "ItemInfo", &Value::Map(item.clone())).unwrap();
+    assert_eq!(td_rp::decode_map_schema(&mut buffer, &config, "ItemInfo").unwrap(), Value::Map(item.clone()));
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_map(&mut buffer, &config, &Value::Map(item)).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("field name expect str but u32"));
+    let err = td_rp::decode_map_schema(&mut buffer, &config, "Item").unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::MissingError);
+
+    let err = td_rp::ConfigBuilder::new()
+        .schema_field("PlayerInfo", "level", 1, "u8")
+        .proto("cmd_enter", 1, &["map<Player>"])
+        .build()
+        .unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(err.extension_error_detail(),
+               Some("proto cmd_enter arg 0 pattern map<Player> refers unknown schema"));
+}
+
+#[test]
+fn test_required_and_default_field() {
+    let config = td_rp::Config::try_new_by_full_str("{ \"field\" : {}, \"schemas\" : { \"Player\" : { \
+            \"name\" : { \"index\" : 1, \"pattern\" : \"str\", \"required\" : true }, \
+            \"level\" : { \"index\" : 2, \"pattern\" : \"u8\", \"default\" : 1 }, \
+            \"tags\" : { \"index\" : 3, \"pattern\" : \"str[]\", \"default\" : [\"new\"] } } }, \
+        \"proto\" : { \"cmd_player\" : { \"args\" : [ \"map<Player>\" ] } } }").unwrap();
+    let name = "cmd_player".to_string();
+    let mut hash_value = HashMap::<String, Value>::new();
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::Map(hash_value.clone())]).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::MissingError);
+    assert_eq!(err.extension_error_detail(), Some("field name is required"));
+
+    hash_value.insert("name".to_string(), Value::from("tickdream".to_string()));
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::Map(hash_value.clone())]).unwrap();
+    hash_value.insert("level".to_string(), Value::U8(1));
+    hash_value.insert("tags".to_string(), Value::AStr(vec![Value::from("new".to_string())]));
+    assert_eq!(td_rp::decode_proto(&mut buffer, &config).unwrap().1, vec![Value::Map(hash_value)]);
+
+    // decode the map from the config without the required field
+    let old = td_rp::ConfigBuilder::new()
+        .schema_field("Player", "level", 2, "u8")
+        .proto("cmd_player", 1, &["map<Player>"])
+        .build()
+        .unwrap();
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &old, &name, vec![Value::Map(HashMap::new())]).unwrap();
+    let err = td_rp::decode_proto(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("field name is required"));
+
+    let err = td_rp::Config::try_new("{ \"level\" : { \"index\" : 2, \"pattern\" : \"u8\", \"default\" : 256 } }", "{}")
+        .unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("field level default 256 is not u8"));
+
+    // the global fields are shared by every plain map
+    let err = td_rp::Config::try_new("{ \"name\" : { \"index\" : 1, \"pattern\" : \"str\", \"required\" : true }, \
+                                        \"level\" : { \"index\" : 2, \"pattern\" : \"u8\", \"default\" : 1 } }", "{}")
+        .unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert_eq!(err.extension_error_detail(),
+               Some("field level required or default is only for schema fields; \
+                     field name required or default is only for schema fields"));
+}
+
+#[test]
+fn test_required_field_not_in_plain_map() {
+    let config = td_rp::Config::try_new_by_full_str("{ \"field\" : { \"name\" : { \"index\" : 1, \"pattern\" : \"str\" }, \
+                      \"msg\" : { \"index\" : 2, \"pattern\" : \"str\" } }, \
+        \"schemas\" : { \"Player\" : { \"name\" : { \"index\" : 1, \"pattern\" : \"str\", \"required\" : true }, \
+                                      \"level\" : { \"index\" : 2, \"pattern\" : \"u8\", \"default\" : 1 } } }, \
+        \"proto\" : { \"cmd_login\" : { \"args\" : [ \"map<Player>\", \"map\" ] }, \
+                      \"cmd_chat\" : { \"args\" : [ \"map\" ] } } }").unwrap();
+    let mut chat = HashMap::<String, Value>::new();
+    chat.insert("msg".to_string(), Value::from("hello".to_string()));
+
+    // the plain maps of both protos share the global fields, the schema ones don't leak in
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_chat".to_string(), vec![Value::Map(chat.clone())]).unwrap();
+    assert_eq!(td_rp::decode_proto(&mut buffer, &config).unwrap().1, vec![Value::Map(chat.clone())]);
+
+    let mut player = HashMap::<String, Value>::new();
+    player.insert("name".to_string(), Value::from("tickdream".to_string()));
+    let args = vec![Value::Map(player.clone()), Value::Map(chat.clone())];
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_login".to_string(), args).unwrap();
+    player.insert("level".to_string(), Value::U8(1));
+    assert_eq!(td_rp::decode_proto(&mut buffer, &config).unwrap().1,
+               vec![Value::Map(player), Value::Map(chat)]);
+}
+
+#[test]
+fn test_enum_field() {
+    let mut config = td_rp::Config::try_new_by_full_str("{ \"enums\" : { \"Quality\" : { \"white\" : 1, \"green\" : 2, \"blue\" : 3 } }, \"field\" : {}, \
+        \"schemas\" : { \"Item\" : { \"quality\" : { \"index\" : 1, \"pattern\" : \"enum<Quality>\", \"default\" : \"white\" }, \
+                                    \"qualities\" : { \"index\" : 2, \"pattern\" : \"enum<Quality>[]\" } } }, \
+        \"proto\" : { \"cmd_item\" : { \"args\" : [ \"enum<Quality>\", \"map<Item>\" ] } } }")
+        .unwrap();
+    let mut hash_value = HashMap::<String, Value>::new();
+    hash_value.insert("qualities".to_string(),
+                      Value::AStr(vec![Value::from("green".to_string()), Value::from("blue".to_string())]));
+    let args = vec![Value::U16(3), Value::Map(hash_value.clone())];
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_item".to_string(), args).unwrap();
+
+    // the enum is u16 on the wire
+    td_rp::decode_str_raw(&mut buffer, td_rp::TYPE_STR).unwrap();
+    test_head_field(&mut buffer, 0, td_rp::TYPE_U16);
+    buffer.set_rpos(0);
+
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    hash_value.insert("quality".to_string(), Value::from("white".to_string()));
+    assert_eq!(val, vec![Value::from("blue".to_string()), Value::Map(hash_value)]);
+
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &"cmd_item".to_string(),
+                                  vec![Value::from("red".to_string()), Value::Map(HashMap::new())])
+        .unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("enum Quality has no name red"));
+
+    // the unknown value is kept as number, and rejected in strict mode
+    let args = vec![Value::U16(9), Value::Map(HashMap::new())];
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_item".to_string(), args.clone()).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val[0], Value::U16(9));
+    config.set_mismatch_policy(td_rp::MismatchPolicy::Strict);
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &"cmd_item".to_string(), args).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+    assert_eq!(err.extension_error_detail(), Some("enum Quality has no value 9"));
+
+    let err = td_rp::ConfigBuilder::new()
+        .enum_values("Quality", &[("white", 1), ("green", 1)])
+        .field("quality", 1, "enum<Color>")
+        .build()
+        .unwrap_err();
+    assert_eq!(err.extension_error_detail(),
+               Some("field quality pattern enum<Color> refers unknown enum; \
+                     enum Quality names green and white share value 1"));
+
+    // the array of enum arrays is converted in every level
+    let config = td_rp::ConfigBuilder::new()
+        .enum_values("Quality", &[("white", 1), ("green", 2)])
+        .proto("cmd_bags", 1, &["enum<Quality>[][]"])
+        .build()
+        .unwrap();
+    let names = Value::AArray(td_rp::TYPE_ASTR,
+                              vec![Value::AStr(vec![Value::from("green".to_string())]), Value::AStr(vec![])]);
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_bags".to_string(), vec![names.clone()]).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, vec![names]);
+}
+
+#[test]
+fn test_optional_proto_args() {
+    let config = td_rp::Config::new("{}", "{ \"cmd_login\" : { \"args\" : [ \"str\", \"u32?\", \"str?\", \"u8[]?\" ] } }").unwrap();
+    let name = "cmd_login".to_string();
+
+    // the null arg is not the end of the args
+    let mut buffer = Buffer::new();
+    let args = vec![Value::from("tickdream".to_string()), Value::Nil, Value::from("token".to_string())];
+    td_rp::encode_proto(&mut buffer, &config, &name, args.clone()).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, vec![args[0].clone(), Value::Nil, args[2].clone(), Value::Nil]);
+
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &name, vec![args[0].clone()]).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, vec![args[0].clone(), Value::Nil, Value::Nil, Value::Nil]);
+
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::Nil]).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_login arg 0 expect str but nil"));
+
+    // the message ends before the required arg
+    let mut buffer = Buffer::new();
+    td_rp::encode_str_raw(&mut buffer, &Value::from(name.clone())).unwrap();
+    td_rp::write_field(&mut buffer, Some(&td_rp::Field::new_nil())).unwrap();
+    let err = td_rp::decode_proto(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_login expect 1 to 4 args but 0"));
+}
+
+#[test]
+fn test_named_proto_args() {
+    let config = td_rp::Config::new("{}", "{ \"cmd_join_guild\" : { \"args\" : [ \
+        { \"name\" : \"player_id\", \"type\" : \"u32\" }, \
+        { \"name\" : \"guild_id\", \"type\" : \"u32\" }, \
+        { \"name\" : \"message\", \"type\" : \"str?\" } ] } }").unwrap();
+    let name = "cmd_join_guild".to_string();
+    let mut args = HashMap::<String, Value>::new();
+    args.insert("guild_id".to_string(), Value::U32(20));
+    args.insert("player_id".to_string(), Value::U32(10));
+
+    // the wire format is positional
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto_named(&mut buffer, &config, &name, args.clone()).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, vec![Value::U32(10), Value::U32(20), Value::Nil]);
+
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &name, vec![Value::U32(10), Value::U32(20)]).unwrap();
+    let (read_name, val) = td_rp::decode_proto_named(&mut buffer, &config).unwrap();
+    assert_eq!(read_name, name);
+    assert_eq!(val, args);
+
+    args.remove("player_id");
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto_named(&mut buffer, &config, &name, args.clone()).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::MissingError);
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_join_guild arg player_id is missing"));
+
+    assert!(config.to_json_string().contains("\"name\": \"guild_id\""));
+}
+
+#[test]
+fn test_proto_direction() {
+    let mut config = td_rp::Config::new("{ \"name\" : { \"index\" : 1, \"pattern\" : \"str\", \"deprecated\" : true, \"desc\" : \"use nick\" } }",
+        "{ \"cmd_login\" : { \"args\" : [ \"str\" ], \"direction\" : \"c2s\", \"desc\" : \"login with account\" }, \
+           \"cmd_kick\" : { \"args\" : [ \"str\" ], \"direction\" : \"s2c\", \"deprecated\" : true }, \
+           \"cmd_chat\" : { \"args\" : [ \"str\" ] } }").unwrap();
+    let login = config.get_proto_by_name(&"cmd_login".to_string()).unwrap();
+    assert_eq!(login.get_direction(), Some(td_rp::Direction::ClientToServer));
+    assert_eq!(login.get_desc(), Some("login with account"));
+    assert!(!login.is_deprecated());
+    assert!(config.get_proto_by_name(&"cmd_kick".to_string()).unwrap().is_deprecated());
+    assert_eq!(config.get_field_by_name(&"name".to_string()).unwrap().get_desc(), Some("use nick"));
+
+    let args = vec![Value::from("tickdream".to_string())];
+    let mut kick = Buffer::new();
+    td_rp::encode_proto(&mut kick, &config, &"cmd_kick".to_string(), args.clone()).unwrap();
+
+    config.set_send_direction(Some(td_rp::Direction::ClientToServer));
+    for name in &["cmd_login", "cmd_chat"] {
+        let mut buffer = Buffer::new();
+        td_rp::encode_proto(&mut buffer, &config, &name.to_string(), args.clone()).unwrap();
+    }
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &"cmd_kick".to_string(), args.clone()).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::DirectionError);
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_kick is s2c but this side sends c2s"));
+    td_rp::decode_proto(&mut kick, &config).unwrap();
+
+    // the server receives the c2s proto only
+    config.set_send_direction(Some(td_rp::Direction::ServerToClient));
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_kick".to_string(), args).unwrap();
+    let err = td_rp::decode_proto(&mut buffer, &config).unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_kick is s2c but this side receives c2s"));
+}
+
+#[test]
+fn test_nested_array() {
+    assert_eq!(td_rp::get_type_by_name("u32[][]"), td_rp::TYPE_AU32 + td_rp::TYPE_STEP);
+    assert_eq!(td_rp::get_pattern_by_type(td_rp::TYPE_AU8 + td_rp::TYPE_STEP * 2), "u8[][][]".to_string());
+    assert_eq!(td_rp::get_type_by_name("nil[][]"), td_rp::TYPE_NIL);
+
+    let config = td_rp::Config::try_new_by_full_str("{ \"schemas\" : { \"Bag\" : { \
+            \"grid\" : { \"index\" : 1, \"pattern\" : \"u8[][]\" } } }, \
+        \"field\" : {}, \"proto\" : { \"cmd_bags\" : { \"args\" : [ \"u32[][]\", \"map<Bag>[]\" ] } } }")
+        .unwrap();
+    let matrix = Value::AArray(td_rp::TYPE_AU32,
+                               vec![Value::AU32(vec![Value::U32(1), Value::U32(2)]), Value::AU32(vec![])]);
+    let mut bag = HashMap::<String, Value>::new();
+    bag.insert("grid".to_string(),
+               Value::AArray(td_rp::TYPE_AU8, vec![Value::AU8(vec![Value::U8(0), Value::U8(1)])]));
+    let args = vec![matrix, Value::AMap(vec![Value::Map(bag)])];
+    let mut buffer = Buffer::new();
+    td_rp::encode_proto(&mut buffer, &config, &"cmd_bags".to_string(), args.clone()).unwrap();
+    let (_, val) = td_rp::decode_proto(&mut buffer, &config).unwrap();
+    assert_eq!(val, args);
+
+    // the element of the inner array is checked too
+    let bad = Value::AArray(td_rp::TYPE_AU32,
+                            vec![Value::AU32(vec![Value::U32(1)]), Value::AU32(vec![Value::U16(2)])]);
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &"cmd_bags".to_string(),
+                                  vec![bad.clone(), Value::AMap(vec![])])
+        .unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_bags arg 0 element 1.0 expect u32 but u16"));
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_field(&mut buffer, &config, &bad).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+
+    // the element of the array of arrays must be array, u32[] has only one representation
+    for bad in vec![Value::AArray(td_rp::TYPE_U32, vec![Value::U32(1)]),
+                    Value::AArray(td_rp::TYPE_NIL, vec![]),
+                    Value::AArray(99, vec![])] {
+        let mut buffer = Buffer::new();
+        let err = td_rp::encode_field(&mut buffer, &config, &bad).unwrap_err();
+        assert_eq!(err.kind(), td_rp::ErrorKind::TypeNotMatchError);
+        assert_eq!(buffer.len(), 0);
+    }
+    let bad = Value::AArray(td_rp::TYPE_AU32, vec![Value::AArray(td_rp::TYPE_U32, vec![])]);
+    let mut buffer = Buffer::new();
+    let err = td_rp::encode_proto(&mut buffer, &config, &"cmd_bags".to_string(), vec![bad, Value::AMap(vec![])])
+        .unwrap_err();
+    assert_eq!(err.extension_error_detail(), Some("proto cmd_bags arg 0 array of u32 is not the array of arrays"));
+}
+
+#[test]
+fn test_check_compatible() {
+    let old = td_rp::ConfigBuilder::new()
+        .field("name", 1, "str")
+        .field("index", 2, "u16")
+        .field("level", 3, "u8")
+        .field("exp", 4, "u32")
+        .field("old", 5, "u32")
+        .schema_field("Item", "count", 1, "u8")
+        .proto("cmd_test_op", 1, &["map"])
+        .proto("cmd_test_op2", 2, &["u8"])
+        .proto("cmd_test_op3", 3, &["u8"])
+        .build()
+        .unwrap();
+    let new = td_rp::ConfigBuilder::new()
+        .field("name", 1, "str")
+        .field("idx", 2, "u16")
+        .field("level", 3, "u16")
+        .field("exp", 6, "u32")
+        .field("gold", 7, "u64")
+        .schema_field("Item", "count", 1, "u16")
+        .proto("cmd_test_op", 1, &["map", "u8"])
+        .proto("cmd_test_op3", 3, &["u8"])
+        .proto("cmd_test_op4", 4, &["u8"])
+        .build()
+        .unwrap();
+    let changes = td_rp::check_compatible(&old, &new);
+    let messages: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(messages, vec!["field Item.count pattern changed from u8 to u16",
+                              "field exp index changed from 4 to 6",
+                              "field index 2 renamed from index to idx",
+                              "field level pattern changed from u8 to u16",
+                              "proto cmd_test_op args changed from [\"map\"] to [\"map\", \"u8\"]",
+                              "proto cmd_test_op2 removed",
+                              "field gold added",
+                              "field old removed",
+                              "proto cmd_test_op4 added"]);
+    assert_eq!(changes.iter().filter(|c| c.is_breaking()).count(), 6);
+    assert_eq!(changes[6], td_rp::Change::FieldAdded("gold".to_string()));
+    assert!(td_rp::check_compatible(&old, &old).is_empty());
+
+    // the old encoder may omit the field which the new decoder requires
+    let old = td_rp::Config::try_new_by_full_str("{ \"field\" : {}, \"schemas\" : { \"Player\" : { \
+        \"name\" : { \"index\" : 1, \"pattern\" : \"str\" } } }, \"proto\" : {} }").unwrap();
+    let new = td_rp::Config::try_new_by_full_str("{ \"field\" : {}, \"schemas\" : { \"Player\" : { \
+        \"name\" : { \"index\" : 1, \"pattern\" : \"str\", \"required\" : true }, \
+        \"level\" : { \"index\" : 2, \"pattern\" : \"u8\", \"required\" : true } } }, \"proto\" : {} }")
+        .unwrap();
+    let changes = td_rp::check_compatible(&old, &new);
+    let messages: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(messages, vec!["field Player.level required changed from false to true",
+                              "field Player.name required changed from false to true",
+                              "field Player.level added"]);
+    assert_eq!(changes.iter().filter(|c| c.is_breaking()).count(), 2);
+
+    let old = td_rp::ConfigBuilder::new()
+        .enum_values("Quality", &[("white", 1), ("green", 2), ("blue", 3)])
+        .build()
+        .unwrap();
+    let new = td_rp::ConfigBuilder::new()
+        .enum_values("Quality", &[("white", 1), ("green", 4), ("purple", 5)])
+        .build()
+        .unwrap();
+    let changes = td_rp::check_compatible(&old, &new);
+    assert_eq!(changes,
+               vec![td_rp::Change::EnumValueRemoved("Quality.blue".to_string()),
+                    td_rp::Change::EnumValueChanged("Quality.green".to_string(), 2, 4)]);
+    assert!(changes.iter().all(|c| c.is_breaking()));
+}
+
+#[test]
+fn test_shared_config_reload() {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let dir = write_protocol_dir("reload", &[
+        ("old.json", "{ \"field\" : { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" } }, \
+                        \"proto\" : { \"cmd_test_op\" : { \"args\" : [ \"map\" ] } } }"),
+        ("new.json", "{ \"field\" : { \"name\" : { \"index\" :    1, \"pattern\" : \"str\" }, \
+                                      \"index\" : { \"index\" :    2, \"pattern\" : \"u16\" } }, \
+                        \"proto\" : { \"cmd_test_op\" : { \"args\" : [ \"map\" ] } } }"),
+        ("bad.json", "{ \"field\" : { \"name\" : { \"index\" :    0, \"pattern\" : \"str\" } }, \"proto\" : {} }"),
+    ]);
+    let mut config = td_rp::Config::from_file(dir.join("old.json")).unwrap();
+    config.set_unknown_key_policy(td_rp::UnknownKeyPolicy::Error);
+    config.set_float_scale(100).unwrap();
+    let shared = Arc::new(td_rp::SharedConfig::new(config));
+    let reported = Arc::new(Mutex::new(vec![]));
+    {
+        let reported = reported.clone();
+        let weak = Arc::downgrade(&shared);
+        shared.on_change(move |changes| {
+            reported.lock().unwrap().extend(changes.iter().map(|c| c.to_string()));
+            // the callback can register another one without deadlock
+            weak.upgrade().unwrap().on_change(|_| ());
+        });
+    }
+
+    // the config got before reload is still the old one
+    let old = shared.get();
+    let changes = {
+        let shared = shared.clone();
+        let path = dir.join("new.json");
+        thread::spawn(move || shared.reload_from_file(path).unwrap()).join().unwrap()
+    };
+    assert_eq!(changes, vec![td_rp::Change::FieldAdded("index".to_string())]);
+    assert_eq!(*reported.lock().unwrap(), vec!["field index added".to_string()]);
+    assert!(old.get_field_by_index(&2).is_none());
+    let new = shared.get();
+    assert!(new.get_field_by_index(&2).is_some());
+    assert_eq!(new.get_unknown_key_policy(), td_rp::UnknownKeyPolicy::Error);
+    assert_eq!(new.get_float_scale(), 100);
+
+    // the invalid protocol is not loaded
+    let err = shared.reload_from_file(dir.join("bad.json")).unwrap_err();
+    assert_eq!(err.kind(), td_rp::ErrorKind::ConfigError);
+    assert!(shared.get().get_field_by_index(&2).is_some());
+    assert_eq!(reported.lock().unwrap().len(), 1);
+}